@@ -0,0 +1,1951 @@
+//! The quickswitch-i3 command line: its flags, and the mode they pick run
+//! against i3 and a menu.
+
+use std::cell::RefCell;
+use std::cmp;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::io;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use clap::{self, Arg, ArgGroup, App, Shell};
+use regex::Regex;
+use serde_json;
+
+use {Align, ClassAliases, Columns, Config, DesktopEntries, DmenuBackend, Ignores, Ipc, KillSignal,
+     Layout, Mapping, Menu, MenuOutput, MenuPreset, RofiScriptBackend, SendOptions, SortOrder,
+     StreamBackend, Template, TerminalBackend, WindowFilter};
+use {CONNECT_RETRIES, DEFAULT_DMENU_COMMAND, DEFAULT_GAP, DEFAULT_ROFI_COMMAND, FLOATING_MARKER,
+     HISTORY_SIZE, LINES_FLAG, MAX_CONNECT_RETRIES, MAX_GAP, ROFI_FORMAT, SMART_TITLE_SUFFIXES};
+use {load_mru, mru_path, run_daemon, sort_by_mru};
+use {application_dirs, boost_history, choose, choose_with_code, config_dir, config_path, connect,
+     count_windows, custom_key_action, dmenu_from_env, escape_markup, find_selection_ignore_case,
+     focused_workspace, fuzzy_filter, get_marks, get_outputs, get_scratchpad_windows, get_tree,
+     get_windows_names, get_workspaces, group_by_workspace, history_path, key_action_command,
+     kill_pid, load_config, load_history, max_class_name_size, max_instance_name_size,
+     max_marks_size, max_pid_size, max_title_size, max_workspace_name_size, menu_available,
+     menu_entry_command, menu_options, number_entries, prepend_last_workspace, push_header,
+     push_unique, record_history, resolve_dmenu_command, save_history, selection_line, send_command,
+     sort_windows, sort_workspaces, split_list, strip_suffixes, workspace_mapping};
+use {bring_command, focus_command, fullscreen_command, mark_command, move_relative_command,
+     move_to_output_command, move_to_scratchpad_command, move_to_workspace_command,
+     new_workspace_command, rename_workspace_command, swap_command, workspace_command};
+
+
+/// Flags choosing what is done with the selection, at most one is given
+static MODES: [&'static str; 23] = [
+    "focus", "menu", "move", "bring", "urgent", "workspace", "last", "new-workspace", "move-to",
+    "move-output", "move-next", "move-prev", "rename", "marks", "set-mark", "swap", "fullscreen",
+    "scratchpad", "to-scratchpad", "command-template", "close", "force-kill", "kill-pid",
+];
+/// Window modes for which the focused window is a fair target, and is listed
+static ACTION_MODES: [&'static str; 13] = [
+    "move", "move-to", "move-output", "move-next", "move-prev", "set-mark", "swap", "fullscreen",
+    "to-scratchpad", "command-template", "close", "force-kill", "kill-pid",
+];
+
+static DEFAULT_MOVE_TO_PROMPT: &'static str = "move to workspace";
+static DEFAULT_MOVE_OUTPUT_PROMPT: &'static str = "move to output";
+static DEFAULT_RENAME_PROMPT: &'static str = "new name";
+static DEFAULT_NEW_WORKSPACE_PROMPT: &'static str = "new workspace";
+static DEFAULT_MARK_PROMPT: &'static str = "mark";
+static DEFAULT_SWAP_PROMPT: &'static str = "swap with";
+
+/// Where what is kept from one run to the next lives, nothing is kept
+/// without them
+#[derive(Debug, Default)]
+struct StateFiles {
+    /// Directory of the history files
+    history_dir: Option<PathBuf>,
+    /// The daemon's focus history
+    mru: Option<PathBuf>,
+}
+
+/// What `run` gathered from the command line and the config file
+struct Settings {
+    ignores: Ignores,
+    aliases: ClassAliases,
+    filter: WindowFilter,
+    workspace_filter: Option<Regex>,
+    template: Option<Template>,
+    command_template: Option<Template>,
+}
+
+/// Settles on a menu backend the first time a menu is shown, runs that
+/// never show one (--last, --json, --list...) don't warn about a menu
+/// program that can't run, nor fail on it
+struct LazyMenu<F> {
+    make: F,
+    menu: RefCell<Option<Box<dyn Menu>>>,
+}
+
+impl<F: Fn() -> Result<Box<dyn Menu>, Box<dyn Error>>> Menu for LazyMenu<F> {
+    fn choose(&self, options: &str, prompt: Option<&str>) -> Result<MenuOutput, Box<dyn Error>> {
+        if self.menu.borrow().is_none() {
+            *self.menu.borrow_mut() = Some((self.make)()?);
+        }
+        self.menu.borrow().as_ref().unwrap().choose(options, prompt)
+    }
+
+    fn indexed(&self) -> bool {
+        self.menu.borrow().as_ref().is_some_and(|m| m.indexed())
+    }
+}
+
+fn compile_regex(matches: &clap::ArgMatches, name: &str) -> Result<Option<Regex>, Box<dyn Error>> {
+    match matches.value_of(name) {
+        Some(pattern) => Regex::new(pattern)
+            .map(Some)
+            .map_err(|e| format!("invalid --{} pattern '{}': {}", name, pattern, e).into()),
+        None => Ok(None),
+    }
+}
+
+fn parse_value<T>(matches: &clap::ArgMatches, name: &str) -> Result<Option<T>, Box<dyn Error>>
+    where T: std::str::FromStr, T::Err: std::fmt::Display
+{
+    match matches.value_of(name) {
+        Some(value) => value.parse()
+            .map(Some)
+            .map_err(|e| format!("invalid value '{}' for --{}: {}", value, name, e).into()),
+        None => Ok(None),
+    }
+}
+
+fn values_of(matches: &clap::ArgMatches, name: &str) -> Vec<String> {
+    matches.values_of(name)
+        .map(|values| values.map(|s| s.to_owned()).collect())
+        .unwrap_or_default()
+}
+
+fn default_prompt(matches: &clap::ArgMatches) -> &'static str {
+    if matches.is_present("workspace") {
+        "workspace"
+    } else if matches.is_present("rename") {
+        "rename"
+    } else if ["move", "move-to", "move-output", "move-next", "move-prev"].iter()
+        .any(|m| matches.is_present(m)) {
+        "move"
+    } else if ["close", "force-kill", "kill-pid"].iter().any(|m| matches.is_present(m)) {
+        "close"
+    } else if matches.is_present("marks") || matches.is_present("set-mark") {
+        "mark"
+    } else if matches.is_present("bring") {
+        "bring"
+    } else if matches.is_present("swap") {
+        "swap"
+    } else if matches.is_present("fullscreen") {
+        "fullscreen"
+    } else if matches.is_present("scratchpad") {
+        "scratchpad"
+    } else if matches.is_present("to-scratchpad") {
+        "hide"
+    } else if matches.is_present("menu") {
+        "menu"
+    } else {
+        "focus"
+    }
+}
+
+fn title_suffixes(matches: &clap::ArgMatches) -> Result<Vec<Regex>, Box<dyn Error>> {
+    let mut patterns = values_of(matches, "strip-suffix");
+    if matches.is_present("smart-titles") {
+        patterns.extend(SMART_TITLE_SUFFIXES.iter().map(|s| s.to_string()));
+    }
+
+    patterns.iter()
+        .map(|p| Regex::new(p)
+             .map_err(|e| format!("invalid --strip-suffix pattern '{}': {}", p, e).into()))
+        .collect()
+}
+
+fn window_filter(matches: &clap::ArgMatches) -> Result<WindowFilter, Box<dyn Error>> {
+    Ok(WindowFilter {
+        any: compile_regex(matches, "filter")?,
+        title: compile_regex(matches, "filter-title")?,
+        class: compile_regex(matches, "filter-class")?,
+    })
+}
+
+fn command_template(matches: &clap::ArgMatches) -> Result<Option<Template>, Box<dyn Error>> {
+    let template: Option<Template> = parse_value(matches, "command-template")?;
+    if template.as_ref().is_some_and(|t| !t.has_placeholders()) {
+        return Err("--command-template must use at least one placeholder, like {id}".into());
+    }
+    Ok(template)
+}
+
+fn ignores(matches: &clap::ArgMatches, config: &Config) -> Ignores {
+    let mut ignores = Ignores::new(!matches.is_present("no-default-ignores"));
+    if matches.is_present("include-docks") {
+        ignores.include_docks();
+    }
+    if matches.is_present("include-unnamed") {
+        ignores.include_unnamed(matches.value_of("unnamed-label").unwrap_or_default());
+    }
+    ignores.extend(&config.ignore_names, &config.ignore_classes);
+    ignores.extend(&split_list(&values_of(matches, "ignore-name")),
+                   &split_list(&values_of(matches, "ignore-class")));
+    ignores
+}
+
+/// rofi passes the selection as the last argument, after a `--` a title like
+/// "- draft" isn't taken for flags. There is none on the first run, retv 0.
+fn separate_selection(mut args: Vec<OsString>, retv: i32) -> Vec<OsString> {
+    let script = args.iter().any(|a| a == "--rofi-script");
+    if script && retv != 0 && args.len() > 2 && !args.iter().any(|a| a == "--") {
+        let last = args.len() - 1;
+        args.insert(last, OsString::from("--"));
+    }
+    args
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("Quickswitch-i3.rs")
+        .version("0.1")
+        .author("Jocelyn B. <kazoomy@gmail.com>")
+        .arg(Arg::with_name("dmenu")
+             .short("d")
+             .long("dmenu")
+             .value_name("DMENU")
+             .help("dmenu command to execute, defaults to $QUICKSWITCH_DMENU or $DMENU")
+             .takes_value(true))
+        .arg(Arg::with_name("menu-preset")
+             .long("menu-preset")
+             .value_name("MENU")
+             .help("menu program to run with the right flags, unless --dmenu is given")
+             .possible_values(&["dmenu", "bemenu", "wofi", "fuzzel"])
+             .conflicts_with("rofi")
+             .takes_value(true))
+        .arg(Arg::with_name("rofi")
+             .long("rofi")
+             .help("use rofi's dmenu mode and select entries by index"))
+        .arg(Arg::with_name("markup")
+             .long("markup")
+             .help("color the class column with rofi's pango markup")
+             .requires("rofi"))
+        .arg(Arg::with_name("markup-color")
+             .long("markup-color")
+             .value_name("COLOR")
+             .help("color of the class column with --markup")
+             .default_value("#888888"))
+        .arg(Arg::with_name("icons")
+             .long("icons")
+             .help("show application icons in rofi")
+             .requires("rofi"))
+        .arg(Arg::with_name("prompt")
+             .short("p")
+             .long("prompt")
+             .value_name("TEXT")
+             .help("menu prompt, defaults to the name of the mode")
+             .takes_value(true))
+        .arg(Arg::with_name("json")
+             .long("json")
+             .help("print the candidates as JSON instead of opening the menu"))
+        .arg(Arg::with_name("fuzzy")
+             .long("fuzzy")
+             .value_name("QUERY")
+             .help("only list the entries fuzzy matching QUERY, acting on a single match at once")
+             .takes_value(true))
+        .arg(Arg::with_name("print-selection")
+             .long("print-selection")
+             .help("print the id, class and title of the selected window once done"))
+        .arg(Arg::with_name("selection-only")
+             .long("selection-only")
+             .help("print the criteria or name of the selection instead of acting on it")
+             .conflicts_with("print-selection"))
+        .arg(Arg::with_name("list")
+             .long("list")
+             .help("print the menu entries instead of opening the menu"))
+        .arg(Arg::with_name("stdin-menu")
+             .long("stdin-menu")
+             .help("write the entries to stdout and read the selection from stdin"))
+        .arg(Arg::with_name("rofi-script")
+             .long("rofi-script")
+             .help("run as a rofi script, with -modi 'switch:quickswitch-i3 --rofi-script'")
+             // a second menu would need a third run, with the first pick forgotten
+             .conflicts_with_all(&["stdin-menu", "tui", "new-workspace", "rename", "move-to",
+                                   "move-output", "set-mark", "swap"]))
+        .arg(Arg::with_name("selection")
+             .value_name("SELECTION")
+             .help("entry rofi passes back to --rofi-script")
+             .index(1)
+             .requires("rofi-script"))
+        .arg(Arg::with_name("tui")
+             .long("tui")
+             .help("pick from a numbered list in the terminal, the default without a menu to run")
+             .conflicts_with("stdin-menu"))
+        .arg(Arg::with_name("generate-completions")
+             .long("generate-completions")
+             .value_name("SHELL")
+             .help("print a completion script for SHELL and exit")
+             .possible_values(&Shell::variants())
+             .takes_value(true))
+        .arg(Arg::with_name("daemon")
+             .long("daemon")
+             .help("track window focus in the background for an exact --sort mru"))
+        .arg(Arg::with_name("socket")
+             .long("socket")
+             .value_name("PATH")
+             .help("i3/sway IPC socket to connect to")
+             .takes_value(true))
+        .arg(Arg::with_name("connect-retries")
+             .long("connect-retries")
+             .value_name("N")
+             .help("attempts to reconnect to i3 before giving up, 3 by default and 20 at most")
+             .takes_value(true))
+        .arg(Arg::with_name("dry-run")
+             .short("n")
+             .long("dry-run")
+             .help("print the i3 commands instead of running them"))
+        .arg(Arg::with_name("verbose")
+             .short("v")
+             .long("verbose")
+             .help("trace config, menu and i3 commands on stderr"))
+        .arg(Arg::with_name("quiet")
+             .short("q")
+             .long("quiet")
+             .help("only print errors, and what --json, --list or --dry-run ask for")
+             .conflicts_with("verbose"))
+        .arg(Arg::with_name("ignore-class")
+             .long("ignore-class")
+             .value_name("CLASS")
+             .help("hide windows of this class (repeatable, comma separated)")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("ignore-name")
+             .long("ignore-name")
+             .value_name("NAME")
+             .help("hide windows with this name (repeatable, comma separated)")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("no-default-ignores")
+             .long("no-default-ignores")
+             .help("don't hide i3bar and the scratchpad by default"))
+        .arg(Arg::with_name("include-docks")
+             .long("include-docks")
+             .help("list docks, panels and popups too, not just normal windows"))
+        .arg(Arg::with_name("include-unnamed")
+             .long("include-unnamed")
+             .help("list windows without a name too, under --unnamed-label"))
+        .arg(Arg::with_name("unnamed-label")
+             .long("unnamed-label")
+             .value_name("TEXT")
+             .help("title shown for windows without a name")
+             .default_value("<unnamed>"))
+        .arg(Arg::with_name("filter")
+             .long("filter")
+             .value_name("REGEX")
+             .help("only list windows whose title or class matches")
+             .takes_value(true))
+        .arg(Arg::with_name("filter-title")
+             .long("filter-title")
+             .value_name("REGEX")
+             .help("only list windows whose title matches")
+             .takes_value(true))
+        .arg(Arg::with_name("filter-class")
+             .long("filter-class")
+             .value_name("REGEX")
+             .help("only list windows whose class matches")
+             .takes_value(true))
+        .arg(Arg::with_name("strip-suffix")
+             .long("strip-suffix")
+             .value_name("REGEX")
+             .help("remove the end of titles matching REGEX (repeatable)")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("smart-titles")
+             .long("smart-titles")
+             .help("remove the application name ending the titles of common browsers and editors"))
+        .arg(Arg::with_name("current-output")
+             .long("current-output")
+             .help("only list windows on the focused output"))
+        .arg(Arg::with_name("current-workspace")
+             .long("current-workspace")
+             .help("only list windows on the focused workspace"))
+        .arg(Arg::with_name("workspace-filter")
+             .long("workspace-filter")
+             .value_name("REGEX")
+             .help("only list workspaces whose name matches")
+             .takes_value(true))
+        .arg(Arg::with_name("max-title-width")
+             .long("max-title-width")
+             .value_name("N")
+             .help("truncate window titles to N characters")
+             .takes_value(true))
+        .arg(Arg::with_name("limit")
+             .long("limit")
+             .value_name("N")
+             .help("list at most N entries, and size the menu to them")
+             .takes_value(true))
+        .arg(Arg::with_name("sort")
+             .long("sort")
+             .value_name("ORDER")
+             .help("order of the window list")
+             .possible_values(&["mru", "alpha", "class"])
+             .default_value("mru"))
+        .arg(Arg::with_name("reverse")
+             .long("reverse")
+             .help("list the entries bottom to top"))
+        .arg(Arg::with_name("number")
+             .long("number")
+             .help("prefix the entries with their position"))
+        .arg(Arg::with_name("sort-workspaces")
+             .long("sort-workspaces")
+             .value_name("ORDER")
+             .help("order of the workspace list, by number by default")
+             .possible_values(&["num", "name", "output"])
+             .takes_value(true))
+        .arg(Arg::with_name("skip-focused")
+             .long("skip-focused")
+             .help("leave the focused window out of the list (default when focusing)"))
+        .arg(Arg::with_name("no-skip-focused")
+             .long("no-skip-focused")
+             .help("keep the focused window in the list when focusing"))
+        .arg(Arg::with_name("gap")
+             .long("gap")
+             .value_name("N")
+             .help("spaces between the columns, 5 by default")
+             .takes_value(true))
+        .arg(Arg::with_name("separator")
+             .long("separator")
+             .value_name("STR")
+             .help("separate the columns with STR instead of aligning them, \\t for a tab")
+             .takes_value(true))
+        .arg(Arg::with_name("align")
+             .long("align")
+             .value_name("SIDE")
+             .help("side of its column the class is aligned to")
+             .possible_values(&["left", "right"])
+             .default_value("left"))
+        .arg(Arg::with_name("marker-focused")
+             .long("marker-focused")
+             .value_name("MARKER")
+             .help("prefix the focused window with MARKER")
+             .takes_value(true))
+        .arg(Arg::with_name("marker-urgent")
+             .long("marker-urgent")
+             .value_name("MARKER")
+             .help("prefix urgent windows and workspaces with MARKER")
+             .takes_value(true))
+        .arg(Arg::with_name("show-floating")
+             .long("show-floating")
+             .help("prefix floating windows with a marker"))
+        .arg(Arg::with_name("show-workspace")
+             .long("show-workspace")
+             .help("show the workspace of each window"))
+        .arg(Arg::with_name("group")
+             .long("group")
+             .help("group windows under a header per workspace"))
+        .arg(Arg::with_name("format")
+             .long("format")
+             .value_name("TEMPLATE")
+             .help("window entry format, using {class}, {instance}, {name}, {id}, {con_id}, \
+                    {workspace}, {mark}, {pid} and {floating}")
+             .takes_value(true))
+        .arg(Arg::with_name("title-only")
+             .long("title-only")
+             .help("show only the window titles")
+             .conflicts_with("class-only"))
+        .arg(Arg::with_name("class-only")
+             .long("class-only")
+             .help("show only the window classes"))
+        .arg(Arg::with_name("show-marks")
+             .long("show-marks")
+             .help("show the marks of each window"))
+        .arg(Arg::with_name("show-pid")
+             .long("show-pid")
+             .help("show the process id of each window"))
+        .arg(Arg::with_name("show-geometry")
+             .long("show-geometry")
+             .help("show the size and position of each window after its title"))
+        .arg(Arg::with_name("show-instance")
+             .long("show-instance")
+             .help("show the WM_CLASS instance of each window"))
+        .arg(Arg::with_name("focus")
+             .short("f")
+             .long("focus"))
+        .arg(Arg::with_name("move")
+             .short("m")
+             .long("move"))
+        .arg(Arg::with_name("menu")
+             .long("menu")
+             .help("list windows then workspaces, rofi's custom keys picking the action"))
+        .arg(Arg::with_name("bring")
+             .long("bring")
+             .help("bring the selected window to the current workspace and focus it"))
+        .arg(Arg::with_name("urgent")
+             .short("u")
+             .long("urgent")
+             .help("jump to an urgent window, only asking if there are several"))
+        .arg(Arg::with_name("clear-urgency")
+             .long("clear-urgency")
+             .help("clear the urgency hint right away when jumping to the window")
+             .requires("urgent"))
+        .arg(Arg::with_name("workspace")
+             .short("w")
+             .long("workspace")
+             .help("switch to a workspace, typing a new name creates it"))
+        .arg(Arg::with_name("last")
+             .long("last")
+             .help("go back to the previous workspace, without a menu"))
+        .arg(Arg::with_name("count-windows")
+             .long("count-windows")
+             .help("show the number of windows of each workspace"))
+        .arg(Arg::with_name("new-workspace")
+             .long("new-workspace")
+             .help("prompt for a name and switch to that new workspace"))
+        .arg(Arg::with_name("move-to")
+             .long("move-to")
+             .help("move the selected window to a workspace picked in a second menu"))
+        .arg(Arg::with_name("move-output")
+             .long("move-output")
+             .help("move the selected window to an output picked in a second menu"))
+        .arg(Arg::with_name("move-next")
+             .long("move-next")
+             .help("move the selected window to the next workspace"))
+        .arg(Arg::with_name("move-prev")
+             .long("move-prev")
+             .help("move the selected window to the previous workspace"))
+        .arg(Arg::with_name("follow")
+             .long("follow")
+             .help("with --move-next and --move-prev, focus the window once moved"))
+        .arg(Arg::with_name("move-to-prompt")
+             .long("move-to-prompt")
+             .value_name("TEXT")
+             .help("prompt of the second menu of --move-to and --move-output")
+             .takes_value(true))
+        .arg(Arg::with_name("rename")
+             .long("rename")
+             .help("rename the selected workspace"))
+        .arg(Arg::with_name("rename-prompt")
+             .long("rename-prompt")
+             .value_name("TEXT")
+             .help("prompt asking for the name with --rename, --new-workspace and --set-mark")
+             .takes_value(true))
+        .arg(Arg::with_name("marks")
+             .long("marks")
+             .help("focus the container holding a mark"))
+        .arg(Arg::with_name("set-mark")
+             .long("set-mark")
+             .help("mark the selected window with a name typed in a second menu"))
+        .arg(Arg::with_name("swap")
+             .long("swap")
+             .help("swap the selected window with one picked in a second menu"))
+        .arg(Arg::with_name("fullscreen")
+             .long("fullscreen")
+             .help("toggle fullscreen on the selected window, focusing it"))
+        .arg(Arg::with_name("no-focus")
+             .long("no-focus")
+             .help("with --fullscreen, don't focus the window first")
+             .requires("fullscreen"))
+        .arg(Arg::with_name("scratchpad")
+             .long("scratchpad")
+             .help("show a window hidden in the scratchpad"))
+        .arg(Arg::with_name("to-scratchpad")
+             .long("to-scratchpad")
+             .help("hide the selected window in the scratchpad"))
+        .arg(Arg::with_name("command-template")
+             .long("command-template")
+             .value_name("COMMAND")
+             .help("i3 command to run on the selected window, with the placeholders of --format")
+             .takes_value(true))
+        .arg(Arg::with_name("close")
+             .short("c")
+             .long("close")
+             .help("close the selected window"))
+        .arg(Arg::with_name("force-kill")
+             .long("force-kill")
+             .help("kill the client owning the selected window"))
+        .arg(Arg::with_name("kill-pid")
+             .long("kill-pid")
+             .help("send a signal to the process of the selected window, if i3 can't close it"))
+        .arg(Arg::with_name("kill-signal")
+             .long("kill-signal")
+             .value_name("SIGNAL")
+             .help("signal --kill-pid sends, KILL by default")
+             .possible_values(&["term", "kill"])
+             .requires("kill-pid")
+             .takes_value(true))
+        // only one action can be carried out on the selection
+        .group(ArgGroup::with_name("mode").args(&MODES))
+}
+
+/// Runs quickswitch-i3 with the process' arguments and environment, the
+/// way the binary does
+pub fn run() -> Result<(), Box<dyn Error>> {
+    let retv = env::var("ROFI_RETV").ok().and_then(|v| v.parse().ok()).unwrap_or(0);
+    let matches = app().get_matches_from(separate_selection(env::args_os().collect(), retv));
+
+    if let Some(shell) = parse_value::<Shell>(&matches, "generate-completions")? {
+        app().gen_completions_to("quickswitch-i3", shell, &mut io::stdout());
+        return Ok(());
+    }
+
+    let verbose = matches.is_present("verbose");
+
+    let config = match config_path() {
+        Some(path) => {
+            if verbose {
+                eprintln!("config file: {}", path.display());
+            }
+            load_config(&path)?
+        }
+        None => Config::default(),
+    };
+    let ignores = ignores(&matches, &config);
+
+    let filter = window_filter(&matches)?;
+    let max_title_width = parse_value::<usize>(&matches, "max-title-width")?;
+    if max_title_width == Some(0) {
+        return Err("--max-title-width must be at least 1".into());
+    }
+    let template: Option<Template> = parse_value(&matches, "format")?;
+    let command_template = command_template(&matches)?;
+    let workspace_filter = compile_regex(&matches, "workspace-filter")?;
+
+    let rofi = matches.is_present("rofi");
+    let preset: Option<MenuPreset> = parse_value(&matches, "menu-preset")?;
+    let env_command = dmenu_from_env();
+    let mut dmenu_command = resolve_dmenu_command(flag_command(&matches, preset),
+                                                  env_command.as_deref(),
+                                                  config.dmenu_command.as_deref(),
+                                                  DEFAULT_DMENU_COMMAND);
+    if rofi {
+        dmenu_command = format!("{} {}", dmenu_command, ROFI_FORMAT);
+    }
+    if matches.is_present("markup") {
+        dmenu_command = format!("{} -markup-rows", dmenu_command);
+    }
+    if matches.is_present("icons") {
+        dmenu_command = format!("{} -show-icons", dmenu_command);
+    }
+    let max_lines = parse_value(&matches, "limit")?;
+    let menu = LazyMenu {
+        make: || -> Result<Box<dyn Menu>, Box<dyn Error>> {
+            if matches.is_present("rofi-script") {
+                return Ok(Box::new(RofiScriptBackend::new(
+                    retv,
+                    matches.value_of("selection").map(|s| s.to_owned()),
+                    matches.is_present("workspace"),
+                    io::stdout(),
+                )));
+            }
+            if matches.is_present("stdin-menu") {
+                return Ok(Box::new(StreamBackend::new(io::BufReader::new(io::stdin()),
+                                                      io::stdout())));
+            }
+            if matches.is_present("tui") || !menu_available(&dmenu_command) {
+                if !matches.is_present("tui") {
+                    // from a key binding nobody would answer, report the menu
+                    // given rather than waiting on a terminal that isn't there
+                    if matches.is_present("dmenu") && !io::stdin().is_terminal() {
+                        return Err(format!("cannot run menu command '{}'", dmenu_command).into());
+                    }
+                    if !matches.is_present("quiet") {
+                        eprintln!("quickswitch-i3: cannot run '{}', falling back to the terminal",
+                                  dmenu_command);
+                    }
+                }
+                return Ok(Box::new(TerminalBackend::new(io::BufReader::new(io::stdin()),
+                                                        io::stderr())));
+            }
+            Ok(Box::new(DmenuBackend {
+                command: dmenu_command.to_owned(),
+                indexed: rofi,
+                verbose: verbose,
+                max_lines: max_lines,
+                // an explicit --dmenu is taken to be dmenu-like
+                lines_flag: match preset {
+                    Some(preset) if !matches.is_present("dmenu") => preset.lines_flag(),
+                    _ => LINES_FLAG,
+                },
+            }))
+        },
+        menu: RefCell::new(None),
+    };
+
+    let retries = cmp::min(parse_value(&matches, "connect-retries")?.unwrap_or(CONNECT_RETRIES),
+                           MAX_CONNECT_RETRIES);
+    if matches.is_present("daemon") {
+        let path = mru_path()
+            .ok_or("could not find a directory for the state file, set XDG_RUNTIME_DIR")?;
+        if verbose {
+            eprintln!("state file: {}", path.display());
+        }
+        return run_daemon(matches.value_of("socket"), &path, retries, verbose);
+    }
+
+    let mut connection = connect(matches.value_of("socket"), retries)?;
+
+    let settings = Settings {
+        ignores: ignores,
+        aliases: ClassAliases::new(&config.class_aliases),
+        filter: filter,
+        workspace_filter: workspace_filter,
+        template: template,
+        command_template: command_template,
+    };
+    let state = StateFiles { history_dir: config_dir(), mru: mru_path() };
+    quickswitch(&matches, &mut connection, &menu, settings, &state, &mut io::stdout())
+}
+
+// A preset or --rofi is given on the command line too, only --dmenu beats
+// them; $DMENU pointing at dmenu must not swallow rofi's flags
+fn flag_command<'a>(matches: &'a clap::ArgMatches, preset: Option<MenuPreset>) -> Option<&'a str> {
+    matches.value_of("dmenu")
+        .or_else(|| preset.map(|p| p.command()))
+        .or_else(|| if matches.is_present("rofi") { Some(DEFAULT_ROFI_COMMAND) } else { None })
+}
+
+// What gets printed, listings and selections, goes to out
+fn quickswitch(matches: &clap::ArgMatches, connection: &mut dyn Ipc, menu: &dyn Menu,
+               settings: Settings, state: &StateFiles, out: &mut dyn io::Write)
+               -> Result<(), Box<dyn Error>> {
+    let Settings { ignores, aliases, filter, workspace_filter, template, command_template } =
+        settings;
+    let send = SendOptions {
+        verbose: matches.is_present("verbose"),
+        dry_run: matches.is_present("dry-run"),
+    };
+
+    if matches.is_present("last") {
+        send_command(connection, "workspace back_and_forth", send, out)?;
+        return Ok(());
+    }
+
+    if matches.is_present("new-workspace") {
+        let prompt = matches.value_of("rename-prompt").unwrap_or(DEFAULT_NEW_WORKSPACE_PROMPT);
+        let (_, name) = choose(menu, &Vec::new(), Some(prompt))?;
+        if !name.is_empty() {
+            send_command(connection, &new_workspace_command(&name), send, out)?;
+        }
+        return Ok(());
+    }
+
+    let workspace_mode = matches.is_present("workspace") || matches.is_present("rename");
+    let history_mode = if workspace_mode {
+        "workspaces"
+    } else if matches.is_present("marks") {
+        "marks"
+    } else {
+        "windows"
+    };
+    let history_file = state.history_dir.as_ref().map(|dir| history_path(dir, history_mode));
+    let mut history = match history_file {
+        Some(ref path) => load_history(path)?,
+        None => Vec::new(),
+    };
+    // rofi parses every row as markup, window labels escape themselves
+    let markup = |label: String| {
+        if matches.is_present("markup") { escape_markup(&label) } else { label }
+    };
+
+    let mut mapping: Mapping = Vec::new();
+    if workspace_mode {
+        let mut workspaces = get_workspaces(connection)?;
+        if let Some(re) = workspace_filter {
+            workspaces.retain(|w| re.is_match(&w.name));
+        }
+        if let Some(order) = parse_value(matches, "sort-workspaces")? {
+            sort_workspaces(&mut workspaces, order);
+        }
+        if matches.is_present("count-windows") {
+            let tree = get_tree(connection)?;
+            count_windows(&mut workspaces, &get_windows_names(&tree, &ignores));
+        }
+        if matches.is_present("json") {
+            writeln!(out, "{}", serde_json::to_string(&workspaces)?)?;
+            return Ok(());
+        }
+        mapping = workspace_mapping(workspaces, matches.value_of("marker-urgent")).into_iter()
+            .map(|(label, workspace)| (markup(label), workspace))
+            .collect();
+        if matches.is_present("workspace") {
+            mapping = prepend_last_workspace(mapping);
+        }
+
+    } else if matches.is_present("marks") {
+        let marks = get_marks(connection)?;
+        if matches.is_present("json") {
+            writeln!(out, "{}", serde_json::to_string(&marks)?)?;
+            return Ok(());
+        }
+        for m in marks {
+            push_unique(&mut mapping, markup(m.name.to_owned()), Box::new(m));
+        }
+
+    } else {
+        let tree = get_tree(connection)?;
+        let mut windows = if matches.is_present("scratchpad") {
+            get_scratchpad_windows(&tree, &ignores)
+        } else {
+            get_windows_names(&tree, &ignores)
+        };
+        windows.retain(|w| filter.matches(w));
+
+        let current_output = matches.is_present("current-output");
+        let current_workspace = matches.is_present("current-workspace");
+        if current_output || current_workspace {
+            let workspaces = get_workspaces(connection)?;
+            if let Some(current) = focused_workspace(&workspaces) {
+                if current_output {
+                    windows.retain(|w| w.output.as_ref() == Some(&current.output));
+                }
+                if current_workspace {
+                    windows.retain(|w| w.workspace.as_ref() == Some(&current.name));
+                }
+            }
+        }
+
+        let focus_mode = !ACTION_MODES.iter().any(|m| matches.is_present(m));
+        if matches.is_present("skip-focused") ||
+           (focus_mode && !matches.is_present("no-skip-focused")) {
+            windows.retain(|w| !w.focused);
+        }
+
+        if matches.is_present("urgent") {
+            windows.retain(|w| w.urgent);
+            // nothing to choose from, don't bother opening the menu
+            let listing = matches.is_present("list") || matches.is_present("json");
+            if windows.is_empty() && !listing {
+                return Ok(());
+            }
+        }
+        let order = parse_value(matches, "sort")?.unwrap_or(SortOrder::Mru);
+        sort_windows(&mut windows, order);
+        // without a daemon there is no state file and the tree order is kept
+        if let (SortOrder::Mru, Some(path)) = (order, state.mru.as_ref()) {
+            sort_by_mru(&mut windows, &load_mru(path)?);
+        }
+        if matches.is_present("json") {
+            writeln!(out, "{}", serde_json::to_string(&windows)?)?;
+            return Ok(());
+        }
+        // only now, filters and sorting went by the full titles
+        let suffixes = title_suffixes(matches)?;
+        if !suffixes.is_empty() {
+            for w in &mut windows {
+                w.name = strip_suffixes(&w.name, &suffixes);
+            }
+        }
+        if matches.is_present("icons") {
+            let desktop = DesktopEntries::scan(&application_dirs());
+            for w in &mut windows {
+                w.icon = w.class_name.as_ref()
+                    .and_then(|class| desktop.lookup(class))
+                    .and_then(|entry| entry.icon.to_owned());
+            }
+        }
+        if !aliases.is_empty() {
+            for w in &mut windows {
+                if let Some(alias) = w.class_name.as_ref().and_then(|c| aliases.resolve(c)) {
+                    w.class_name = Some(alias.to_owned());
+                }
+            }
+        }
+        let gap = cmp::min(parse_value(matches, "gap")?.unwrap_or(DEFAULT_GAP), MAX_GAP);
+        let max_title_width = parse_value(matches, "max-title-width")?;
+        let layout = Layout {
+            padding: max_class_name_size(&windows) + gap,
+            max_title_width: max_title_width,
+            marker_focused: matches.value_of("marker-focused").map(|s| s.to_owned()),
+            marker_urgent: matches.value_of("marker-urgent").map(|s| s.to_owned()),
+            marker_floating: if matches.is_present("show-floating") {
+                Some(FLOATING_MARKER.to_owned())
+            } else {
+                None
+            },
+            workspace_padding: if matches.is_present("show-workspace") {
+                Some(max_workspace_name_size(&windows) + gap)
+            } else {
+                None
+            },
+            instance_padding: if matches.is_present("show-instance") {
+                Some(max_instance_name_size(&windows) + gap)
+            } else {
+                None
+            },
+            marks_padding: if matches.is_present("show-marks") {
+                Some(max_marks_size(&windows) + gap)
+            } else {
+                None
+            },
+            pid_padding: if matches.is_present("show-pid") {
+                Some(max_pid_size(&windows) + gap)
+            } else {
+                None
+            },
+            title_padding: if matches.is_present("show-geometry") {
+                Some(max_title_size(&windows, max_title_width) + gap)
+            } else {
+                None
+            },
+            columns: if matches.is_present("title-only") {
+                Columns::TitleOnly
+            } else if matches.is_present("class-only") {
+                Columns::ClassOnly
+            } else {
+                Columns::All
+            },
+            align: parse_value(matches, "align")?.unwrap_or(Align::Left),
+            markup_color: if matches.is_present("markup") {
+                matches.value_of("markup-color").map(|s| s.to_owned())
+            } else {
+                None
+            },
+            gap: gap,
+            // typing a tab in an i3 binding is no fun
+            separator: matches.value_of("separator").map(|s| s.replace("\\t", "\t")),
+            template: template,
+        };
+
+        if matches.is_present("group") {
+            for (workspace, windows) in group_by_workspace(windows) {
+                let title = format!("Workspace {}", workspace.unwrap_or_default());
+                push_header(&mut mapping, &markup(title));
+                for w in windows {
+                    push_unique(&mut mapping, w.label(&layout), Box::new(w));
+                }
+            }
+        } else {
+            for w in windows {
+                push_unique(&mut mapping, w.label(&layout), Box::new(w));
+            }
+        }
+
+        if matches.is_present("menu") {
+            let windows = mapping;
+            mapping = Vec::new();
+            push_header(&mut mapping, "Windows");
+            mapping.extend(windows);
+            push_header(&mut mapping, "Workspaces");
+            let mut workspaces = get_workspaces(connection)?;
+            if let Some(re) = workspace_filter {
+                workspaces.retain(|w| re.is_match(&w.name));
+            }
+            // a workspace named like a window label must not shadow it
+            let marker = matches.value_of("marker-urgent");
+            for (label, workspace) in workspace_mapping(workspaces, marker) {
+                if let Some(workspace) = workspace {
+                    push_unique(&mut mapping, markup(label), workspace);
+                }
+            }
+        }
+
+    }
+    // reordering would scatter the groups
+    if !matches.is_present("group") && !matches.is_present("menu") {
+        boost_history(&mut mapping, &history);
+    }
+    let fuzzy = matches.value_of("fuzzy");
+    if let Some(query) = fuzzy {
+        mapping = fuzzy_filter(mapping, query);
+        if mapping.is_empty() {
+            return Err(format!("nothing matches '{}'", query).into());
+        }
+    }
+    // after sorting and history, so the most relevant entries are kept
+    if let Some(limit) = parse_value(matches, "limit")? {
+        mapping.truncate(limit);
+    }
+    // the mapping itself is reversed, rofi indices keep pointing at the
+    // right entries
+    if matches.is_present("reverse") {
+        mapping.reverse();
+    }
+    // labels are numbered in place, so selections still resolve exactly
+    if matches.is_present("number") {
+        number_entries(&mut mapping);
+    }
+
+    if matches.is_present("list") {
+        writeln!(out, "{}", menu_options(&mapping))?;
+        return Ok(());
+    }
+
+    let prompt = matches.value_of("prompt").unwrap_or_else(|| default_prompt(matches));
+    // a single fuzzy match is what the query was for, and a single urgent
+    // window what --urgent jumps to, no need to confirm either
+    let mut entries = mapping.iter().filter(|entry| entry.1.is_some());
+    let single = match (entries.next(), entries.next()) {
+        (Some(entry), None) if fuzzy.is_some() || matches.is_present("urgent") => Some(entry),
+        _ => None,
+    };
+    let (selection, text, exit_code) = match single {
+        Some(entry) => (entry.1.as_ref().map(|s| s.as_ref()), entry.0.to_owned(), None),
+        None => choose_with_code(menu, &mapping, Some(prompt))?,
+    };
+    // a cancelled menu comes back empty too, nothing to do either way
+    if selection.is_none() && text.is_empty() {
+        return Ok(());
+    }
+
+    if let (Some(res), Some(path)) = (selection, history_file.as_ref()) {
+        record_history(&mut history, &res.to_select_string(), HISTORY_SIZE);
+        save_history(path, &history)?;
+    }
+
+    // someone else sends the command, all they need is what to apply it to
+    if matches.is_present("selection-only") {
+        if let Some(res) = selection {
+            writeln!(out, "{}", res.to_select_string())?;
+        }
+        return Ok(());
+    }
+
+    // taken now, the swap mode trims the mapping the selection points into
+    let printed = selection.and_then(|s| s.as_window()).map(selection_line)
+        .filter(|_| matches.is_present("print-selection"));
+
+    // rofi's custom keys pick the action on the spot, for windows only
+    let key_action = exit_code.and_then(custom_key_action)
+        .filter(|_| selection.and_then(|s| s.as_window()).is_some());
+
+    if let (Some(action), Some(res)) = (key_action, selection) {
+        send_command(connection, &key_action_command(action, res), send, out)?;
+
+    } else if matches.is_present("menu") {
+        let action = exit_code.and_then(custom_key_action);
+        if let Some(command) = selection.and_then(|s| menu_entry_command(s, action)) {
+            send_command(connection, &command, send, out)?;
+        }
+
+    } else if matches.is_present("workspace") {
+        // "web" typed for "Web" switches rather than creating a new one
+        let command = match selection.or_else(|| find_selection_ignore_case(&mapping, &text)) {
+            // ‹last› selects `back_and_forth`, a keyword rather than a name
+            Some(entry) if entry.as_workspace().is_none() => {
+                format!("workspace {}", entry.to_select_string())
+            }
+            Some(entry) => workspace_command(&entry.to_select_string()),
+            None => workspace_command(&text),
+        };
+        send_command(connection, &command, send, out)?;
+
+    } else if matches.is_present("rename") {
+        if let Some(res) = selection {
+            let prompt = matches.value_of("rename-prompt").unwrap_or(DEFAULT_RENAME_PROMPT);
+            let (_, new_name) = choose(menu, &Vec::new(), Some(prompt))?;
+            if !new_name.is_empty() {
+                send_command(connection, &rename_workspace_command(res, &new_name), send, out)?;
+            }
+        }
+    } else if matches.is_present("move") {
+        if let Some(res) = selection {
+            let command = format!("{} move workspace current", res.to_select_string());
+            send_command(connection, &command, send, out)?;
+        }
+    } else if matches.is_present("move-to") {
+        if let Some(res) = selection {
+            let workspaces: Mapping = workspace_mapping(get_workspaces(connection)?, None)
+                .into_iter()
+                .map(|(label, workspace)| (markup(label), workspace))
+                .collect();
+            let prompt = matches.value_of("move-to-prompt").unwrap_or(DEFAULT_MOVE_TO_PROMPT);
+            let (target, text) = choose(menu, &workspaces, Some(prompt))?;
+            // free text names a new workspace, nothing at all cancels
+            let target = target.map(|t| t.to_select_string()).unwrap_or(text);
+            if !target.is_empty() {
+                send_command(connection, &move_to_workspace_command(res, &target), send, out)?;
+            }
+        }
+    } else if matches.is_present("bring") {
+        if let Some(res) = selection {
+            send_command(connection, &bring_command(res), send, out)?;
+        }
+    } else if matches.is_present("move-next") || matches.is_present("move-prev") {
+        if let Some(res) = selection {
+            let next = matches.is_present("move-next");
+            let command = move_relative_command(res, next, matches.is_present("follow"));
+            send_command(connection, &command, send, out)?;
+        }
+    } else if matches.is_present("move-output") {
+        if let Some(res) = selection {
+            let mut outputs: Mapping = Vec::new();
+            for o in get_outputs(connection)? {
+                push_unique(&mut outputs, o.name.to_owned(), Box::new(o));
+            }
+            let prompt = matches.value_of("move-to-prompt").unwrap_or(DEFAULT_MOVE_OUTPUT_PROMPT);
+            let (target, _) = choose(menu, &outputs, Some(prompt))?;
+            if let Some(target) = target {
+                let command = move_to_output_command(res, &target.to_select_string());
+                send_command(connection, &command, send, out)?;
+            }
+        }
+    } else if matches.is_present("set-mark") {
+        if let Some(res) = selection {
+            let prompt = matches.value_of("rename-prompt").unwrap_or(DEFAULT_MARK_PROMPT);
+            let (_, mark) = choose(menu, &Vec::new(), Some(prompt))?;
+            // an empty name would be rejected by i3, take it as cancelling
+            if !mark.is_empty() {
+                send_command(connection, &mark_command(res, &mark), send, out)?;
+            }
+        }
+    } else if matches.is_present("swap") {
+        if let Some(first) = selection.and_then(|s| s.as_window()).cloned() {
+            // a window can't be swapped with itself
+            mapping.retain(|(_, item)| {
+                item.as_ref().and_then(|i| i.as_window()).is_none_or(|w| w.con_id != first.con_id)
+            });
+            let (second, _) = choose(menu, &mapping, Some(DEFAULT_SWAP_PROMPT))?;
+            if let Some(second) = second.and_then(|s| s.as_window()) {
+                send_command(connection, &swap_command(&first, second), send, out)?;
+            }
+        }
+    } else if matches.is_present("fullscreen") {
+        if let Some(res) = selection {
+            let follow = !matches.is_present("no-focus");
+            send_command(connection, &fullscreen_command(res, follow), send, out)?;
+        }
+    } else if matches.is_present("scratchpad") {
+        if let Some(res) = selection {
+            let command = format!("{} scratchpad show", res.to_select_string());
+            send_command(connection, &command, send, out)?;
+        }
+    } else if matches.is_present("to-scratchpad") {
+        if let Some(res) = selection {
+            send_command(connection, &move_to_scratchpad_command(res), send, out)?;
+        }
+    } else if let Some(template) = command_template {
+        // substituted as is, quoting is up to the template
+        if let Some(window) = selection.and_then(|s| s.as_window()) {
+            send_command(connection, &template.render(window), send, out)?;
+        }
+    } else if matches.is_present("kill-pid") {
+        if let Some(window) = selection.and_then(|s| s.as_window()) {
+            let signal = parse_value(matches, "kill-signal")?.unwrap_or(KillSignal::Kill);
+            kill_pid(window, signal, send, out)?;
+        }
+    } else if matches.is_present("close") || matches.is_present("force-kill") {
+        let kill = if matches.is_present("force-kill") { "kill client" } else { "kill" };
+        if let Some(res) = selection {
+            send_command(connection, &format!("{} {}", res.to_select_string(), kill), send, out)?;
+        }
+    } else {
+        // focus is the default mode, --marks included; i3 follows focus
+        // across workspaces
+        if let Some(res) = selection {
+            let clear = matches.is_present("clear-urgency");
+            send_command(connection, &focus_command(res, clear), send, out)?;
+        }
+    }
+    // asked for explicitly, --quiet doesn't silence it
+    if let Some(line) = printed {
+        writeln!(out, "{}", line)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use {Mark, MenuOutput, MockBackend, MockConnection, Window, Workspace};
+
+    // Parses args the way the binary does, and runs them against i3 without
+    // any history; returns what would have been printed
+    fn run_with(args: &[&str], i3: &mut MockConnection, menu: &dyn Menu)
+                -> Result<String, Box<dyn Error>> {
+        run_in(&StateFiles::default(), args, i3, menu)
+    }
+
+    fn run_in(state: &StateFiles, args: &[&str], i3: &mut MockConnection, menu: &dyn Menu)
+              -> Result<String, Box<dyn Error>> {
+        let args = ["quickswitch-i3"].iter().chain(args);
+        let matches = app().get_matches_from_safe(args)?;
+        let settings = Settings {
+            ignores: ignores(&matches, &Config::default()),
+            aliases: ClassAliases::default(),
+            filter: window_filter(&matches)?,
+            workspace_filter: compile_regex(&matches, "workspace-filter")?,
+            template: parse_value(&matches, "format")?,
+            command_template: command_template(&matches)?,
+        };
+        let mut out = Vec::new();
+        quickswitch(&matches, i3, menu, settings, state, &mut out)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    fn answer(choice: &str) -> MockBackend {
+        MockBackend { choice: choice.to_owned() }
+    }
+
+    // Answers choice, keeping the options and prompts it was shown
+    struct Recorder {
+        choice: String,
+        shown: RefCell<Vec<String>>,
+        prompts: RefCell<Vec<Option<String>>>,
+    }
+
+    impl Menu for Recorder {
+        fn choose(&self, options: &str, prompt: Option<&str>)
+                  -> Result<MenuOutput, Box<dyn Error>> {
+            self.shown.borrow_mut().push(options.to_owned());
+            self.prompts.borrow_mut().push(prompt.map(|p| p.to_owned()));
+            Ok(MenuOutput { text: self.choice.to_owned(), exit_code: None })
+        }
+    }
+
+    fn recorder(choice: &str) -> Recorder {
+        Recorder {
+            choice: choice.to_owned(),
+            shown: RefCell::new(Vec::new()),
+            prompts: RefCell::new(Vec::new()),
+        }
+    }
+
+    // Picks choice with one of rofi's custom keys
+    struct Key {
+        choice: String,
+        exit_code: i32,
+    }
+
+    impl Menu for Key {
+        fn choose(&self, _options: &str, _prompt: Option<&str>)
+                  -> Result<MenuOutput, Box<dyn Error>> {
+            Ok(MenuOutput { text: self.choice.to_owned(), exit_code: Some(self.exit_code) })
+        }
+    }
+
+    fn key(choice: &str, exit_code: i32) -> Key {
+        Key { choice: choice.to_owned(), exit_code: exit_code }
+    }
+
+    // A menu program picking the line equal to choice, whatever it is asked
+    fn menu_script(choice: &str) -> DmenuBackend {
+        DmenuBackend {
+            command: format!("sh -c 'grep -x -F \"{}\"'", choice),
+            indexed: false,
+            verbose: false,
+            max_lines: None,
+            lines_flag: LINES_FLAG,
+        }
+    }
+
+    fn workspace(name: &str, num: i32, output: &str) -> Workspace {
+        Workspace {
+            name: name.to_owned(),
+            num: num,
+            output: output.to_owned(),
+            focused: false,
+            urgent: false,
+            window_count: None,
+        }
+    }
+
+    fn window(id: i32, class: &str, name: &str, workspace: &str) -> Window {
+        Window {
+            id: Some(id),
+            con_id: i64::from(id) + 1000,
+            name: name.to_owned(),
+            class_name: Some(class.to_owned()),
+            instance: None,
+            focused: false,
+            urgent: false,
+            floating: false,
+            workspace: Some(workspace.to_owned()),
+            output: None,
+            marks: Vec::new(),
+            rect: (0, 0, 800, 600),
+            pid: None,
+            icon: None,
+        }
+    }
+
+    fn i3() -> MockConnection {
+        let mut emacs = window(3, "Emacs", "main.rs", "web site");
+        emacs.output = Some("HDMI-1".to_owned());
+        MockConnection {
+            windows: vec![
+                window(1, "Firefox", "Mozilla Firefox", "1"),
+                window(2, "URxvt", "~/src", "1"),
+                emacs,
+            ],
+            workspaces: vec![
+                workspace("1", 1, "eDP-1"),
+                workspace("2", 2, "eDP-1"),
+                workspace("web site", -1, "HDMI-1"),
+            ],
+            ..MockConnection::default()
+        }
+    }
+
+    #[test]
+    fn workspace_mode_switches_to_the_chosen_workspace() {
+        let mut i3 = i3();
+        run_with(&["--workspace"], &mut i3, &answer("web site")).unwrap();
+        assert_eq!(i3.commands, vec!["workspace \"web site\""]);
+    }
+
+    #[test]
+    fn workspace_mode_creates_a_typed_workspace() {
+        let mut i3 = i3();
+        run_with(&["--workspace"], &mut i3, &answer("mail")).unwrap();
+        assert_eq!(i3.commands, vec!["workspace \"mail\""]);
+    }
+
+    #[test]
+    fn focus_is_the_default_mode() {
+        for args in &[&["--format", "{name}"][..], &["--focus", "--format", "{name}"]] {
+            let mut i3 = i3();
+            run_with(args, &mut i3, &answer("main.rs")).unwrap();
+            assert_eq!(i3.commands, vec!["[id=\"3\"] focus"], "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn unreachable_i3_is_an_error() {
+        let mut i3 = i3();
+        i3.tree_error = Some("broken pipe".to_owned());
+        let e = run_with(&[], &mut i3, &answer("main.rs")).unwrap_err();
+        assert_eq!(e.to_string(), "could not get the window tree from i3: broken pipe");
+        assert!(i3.commands.is_empty());
+    }
+
+    #[test]
+    fn closing_kills_the_chosen_window() {
+        for &(mode, command) in &[("--close", "[id=\"3\"] kill"),
+                                  ("--force-kill", "[id=\"3\"] kill client")] {
+            let mut i3 = i3();
+            run_with(&[mode, "--format", "{name}"], &mut i3, &answer("main.rs")).unwrap();
+            assert_eq!(i3.commands, vec![command]);
+        }
+    }
+
+    #[test]
+    fn closing_nothing_sends_nothing() {
+        for mode in &["--close", "--force-kill"] {
+            let mut i3 = i3();
+            run_with(&[mode], &mut i3, &answer("")).unwrap();
+            assert!(i3.commands.is_empty(), "{}", mode);
+        }
+    }
+
+    #[test]
+    fn focused_window_is_not_offered_to_switch_to() {
+        let mut i3 = i3();
+        i3.windows[0].focused = true;
+        let menu = recorder("~/src");
+        run_with(&["--format", "{name}"], &mut i3, &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["~/src\nmain.rs"]);
+        assert_eq!(i3.commands, vec!["[id=\"2\"] focus"]);
+
+        let menu = recorder("");
+        run_with(&["--format", "{name}", "--no-skip-focused"], &mut i3, &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["Mozilla Firefox\n~/src\nmain.rs"]);
+    }
+
+    #[test]
+    fn lone_urgent_window_is_focused_without_asking() {
+        let mut i3 = i3();
+        i3.windows[2].urgent = true;
+        let menu = recorder("");
+        run_with(&["--urgent"], &mut i3, &menu).unwrap();
+        assert!(menu.shown.borrow().is_empty());
+        assert_eq!(i3.commands, vec!["[id=\"3\"] focus"]);
+    }
+
+    #[test]
+    fn several_urgent_windows_are_asked_for() {
+        let mut i3 = i3();
+        i3.windows[1].urgent = true;
+        i3.windows[2].urgent = true;
+        let menu = recorder("main.rs");
+        run_with(&["--urgent", "--format", "{name}"], &mut i3, &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["~/src\nmain.rs"]);
+        assert_eq!(i3.commands, vec!["[id=\"3\"] focus"]);
+    }
+
+    #[test]
+    fn no_urgent_window_sends_nothing() {
+        let mut i3 = i3();
+        let menu = recorder("main.rs");
+        run_with(&["--urgent"], &mut i3, &menu).unwrap();
+        assert!(menu.shown.borrow().is_empty());
+        assert!(i3.commands.is_empty());
+    }
+
+    #[test]
+    fn each_mode_has_its_prompt() {
+        let prompts = |args: &[&str]| {
+            let menu = recorder("");
+            run_with(args, &mut i3(), &menu).unwrap();
+            menu.prompts.into_inner()
+        };
+        assert_eq!(prompts(&[]), vec![Some("focus".to_owned())]);
+        assert_eq!(prompts(&["--move"]), vec![Some("move".to_owned())]);
+        assert_eq!(prompts(&["--workspace"]), vec![Some("workspace".to_owned())]);
+        assert_eq!(prompts(&["--workspace", "--prompt", "go to"]), vec![Some("go to".to_owned())]);
+    }
+
+    #[test]
+    fn json_has_the_fields_scripts_rely_on() {
+        let mut w = window(1, "Firefox", "Mozilla Firefox", "1");
+        w.urgent = true;
+        assert_eq!(serde_json::to_string(&[w]).unwrap(),
+                   "[{\"id\":1,\"con_id\":1001,\"name\":\"Mozilla Firefox\",\"class\":\"Firefox\",\
+                    \"instance\":null,\"focused\":false,\"urgent\":true,\"floating\":false,\
+                    \"workspace\":\"1\",\"output\":null,\"marks\":[],\"rect\":[0,0,800,600]}]");
+
+        assert_eq!(serde_json::to_string(&[workspace("web site", -1, "HDMI-1")]).unwrap(),
+                   "[{\"name\":\"web site\",\"num\":-1,\"output\":\"HDMI-1\",\"focused\":false,\
+                    \"urgent\":false}]");
+    }
+
+    #[test]
+    fn list_prints_what_the_menu_would_show() {
+        let args = ["--sort", "class", "--show-workspace", "--marker-focused", "*"];
+        let menu = recorder("");
+        run_with(&args, &mut i3(), &menu).unwrap();
+
+        let list = [&args[..], &["--list"]].concat();
+        let listed = run_with(&list, &mut i3(), &recorder("")).unwrap();
+        assert_eq!(listed, format!("{}\n", menu.shown.borrow()[0]));
+        assert!(listed.starts_with("  web site     Emacs       main.rs\n"), "{}", listed);
+    }
+
+    #[test]
+    fn only_one_mode_at_a_time() {
+        let err = app().get_matches_from_safe(["quickswitch-i3", "--move", "--workspace"])
+            .unwrap_err();
+        assert_eq!(err.kind, clap::ErrorKind::ArgumentConflict);
+        assert!(app().get_matches_from_safe(["quickswitch-i3", "--rename", "--close"]).is_err());
+        assert!(app().get_matches_from_safe(["quickswitch-i3", "--workspace"]).is_ok());
+    }
+
+    #[test]
+    fn bash_completions_know_the_flags() {
+        let mut script = Vec::new();
+        app().gen_completions_to("quickswitch-i3", Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        for flag in &["--workspace", "--move", "--dmenu", "--generate-completions"] {
+            assert!(script.contains(flag), "{} missing", flag);
+        }
+    }
+
+    #[test]
+    fn last_selection_is_listed_first_next_time() {
+        let dir = env::temp_dir().join(format!("quickswitch-i3-history-{}", process::id()));
+        let state = StateFiles { history_dir: Some(dir.clone()), ..StateFiles::default() };
+        let menu = recorder("main.rs");
+        run_in(&state, &["--format", "{name}"], &mut i3(), &menu).unwrap();
+        run_in(&state, &["--format", "{name}"], &mut i3(), &menu).unwrap();
+        let shown = menu.shown.into_inner();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(shown[0], "Mozilla Firefox\n~/src\nmain.rs");
+        assert_eq!(shown[1], "main.rs\nMozilla Firefox\n~/src");
+    }
+
+    #[test]
+    fn unreadable_history_is_an_error() {
+        let state = StateFiles {
+            history_dir: Some(PathBuf::from("/dev/null")),
+            ..StateFiles::default()
+        };
+        let e = run_in(&state, &["--format", "{name}"], &mut i3(), &answer("main.rs")).unwrap_err();
+        assert!(e.to_string().starts_with("could not read history file /dev/null/history-windows"),
+                "{}", e);
+    }
+
+    #[test]
+    fn windows_follow_the_daemon_state_file() {
+        let path = env::temp_dir().join(format!("quickswitch-i3-mru-{}", process::id()));
+        fs::write(&path, "1003\n1002\n").unwrap();
+        let state = StateFiles { mru: Some(path.clone()), ..StateFiles::default() };
+        let menu = recorder("");
+        run_in(&state, &["--format", "{name}"], &mut i3(), &menu).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["main.rs\n~/src\nMozilla Firefox"]);
+
+        // no daemon running is no state file
+        let state = StateFiles { mru: Some(path), ..StateFiles::default() };
+        let menu = recorder("");
+        run_in(&state, &["--format", "{name}"], &mut i3(), &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["Mozilla Firefox\n~/src\nmain.rs"]);
+    }
+
+    #[test]
+    fn scratchpad_mode_shows_a_hidden_window() {
+        let mut i3 = i3();
+        i3.windows.push(window(4, "Keepassxc", "passwords", "__i3_scratch"));
+        let menu = recorder("passwords");
+        run_with(&["--scratchpad", "--format", "{name}"], &mut i3, &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["passwords"]);
+        assert_eq!(i3.commands, vec!["[id=\"4\"] scratchpad show"]);
+    }
+
+    #[test]
+    fn to_scratchpad_hides_a_normal_window() {
+        let mut i3 = i3();
+        i3.windows.push(window(4, "Keepassxc", "passwords", "__i3_scratch"));
+        let menu = recorder("~/src");
+        run_with(&["--to-scratchpad", "--format", "{name}"], &mut i3, &menu).unwrap();
+        assert!(!menu.shown.borrow()[0].contains("passwords"));
+        assert_eq!(i3.commands, vec!["[id=\"2\"] move scratchpad"]);
+    }
+
+    #[test]
+    fn marks_mode_focuses_the_marked_container() {
+        let mut i3 = i3();
+        i3.marks = vec![Mark { name: "mail".to_owned() }, Mark { name: "music".to_owned() }];
+        let menu = recorder("music");
+        run_with(&["--marks"], &mut i3, &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["mail\nmusic"]);
+        assert_eq!(i3.commands, vec!["[con_mark=\"^music$\"] focus"]);
+    }
+
+    #[test]
+    fn set_mark_asks_for_the_name() {
+        let mut i3 = i3();
+        run_with(&["--set-mark", "--format", "{name}"], &mut i3, &answer("~/src")).unwrap();
+        assert_eq!(i3.commands, vec!["[id=\"2\"] mark \"~/src\""]);
+
+        let mut i3 = MockConnection { windows: vec![window(2, "URxvt", "~/src", "1")],
+                                      ..MockConnection::default() };
+        run_with(&["--set-mark"], &mut i3, &answer("")).unwrap();
+        assert!(i3.commands.is_empty());
+    }
+
+    #[test]
+    fn cancelled_menu_does_nothing() {
+        for mode in &["--move", "--rename", "--set-mark"] {
+            let mut i3 = i3();
+            run_with(&[mode], &mut i3, &answer("\n")).unwrap();
+            assert!(i3.commands.is_empty(), "{}", mode);
+        }
+    }
+
+    #[test]
+    fn typed_workspace_names_ignore_case() {
+        let mut i3 = i3();
+        i3.workspaces[2].name = "Web".to_owned();
+        run_with(&["--workspace"], &mut i3, &answer("web")).unwrap();
+        run_with(&["--workspace"], &mut i3, &answer("webs")).unwrap();
+        assert_eq!(i3.commands, vec!["workspace \"Web\"", "workspace \"webs\""]);
+    }
+
+    #[test]
+    fn dry_run_issues_no_command() {
+        let mut i3 = i3();
+        let out = run_with(&["--dry-run", "--workspace"], &mut i3, &answer("web site")).unwrap();
+        assert_eq!(out, "workspace \"web site\"\n");
+        let out = run_with(&["-n", "--format", "{name}"], &mut i3, &answer("~/src")).unwrap();
+        assert_eq!(out, "[id=\"2\"] focus\n");
+        assert!(i3.commands.is_empty());
+    }
+
+    #[test]
+    fn runs_without_verbose_write_nothing_out() {
+        let mut i3 = i3();
+        let out = run_with(&["--workspace"], &mut i3, &answer("web site")).unwrap();
+        assert_eq!(out, "");
+        let out = run_with(&["--format", "{name}"], &mut i3, &answer("~/src")).unwrap();
+        assert_eq!(out, "");
+        assert_eq!(i3.commands, vec!["workspace \"web site\"", "[id=\"2\"] focus"]);
+    }
+
+    #[test]
+    fn command_template_is_filled_in_with_the_window() {
+        let mut i3 = i3();
+        let template = "[id={id}] move workspace current; [con_id={con_id}] focus";
+        let args = ["--format", "{name}", "--command-template", template];
+        run_with(&args, &mut i3, &answer("main.rs")).unwrap();
+        assert_eq!(i3.commands, vec!["[id=3] move workspace current; [con_id=1003] focus"]);
+    }
+
+    #[test]
+    fn command_template_without_placeholders_is_rejected() {
+        let mut i3 = i3();
+        let err = run_with(&["--command-template", "kill"], &mut i3, &answer("main.rs"))
+            .unwrap_err();
+        assert!(err.to_string().contains("at least one placeholder"), "{}", err);
+        assert!(i3.commands.is_empty());
+    }
+
+    #[test]
+    fn limit_keeps_the_first_entries() {
+        let menu = recorder("");
+        run_with(&["--format", "{name}", "--limit", "2"], &mut i3(), &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["Mozilla Firefox\n~/src"]);
+    }
+
+    #[test]
+    fn reverse_lists_bottom_to_top() {
+        let out = run_with(&["--list", "--format", "{name}", "--reverse"], &mut i3(), &answer(""))
+            .unwrap();
+        assert_eq!(out, "main.rs\n~/src\nMozilla Firefox\n");
+    }
+
+    #[test]
+    fn gap_separates_classes_from_titles() {
+        let gaps = [("1", "Firefox Mozilla Firefox"), ("3", "Firefox   Mozilla Firefox")];
+        for &(gap, expected) in &gaps {
+            let menu = recorder("");
+            run_with(&["--gap", gap], &mut i3(), &menu).unwrap();
+            let first = menu.shown.borrow()[0].lines().next().unwrap().to_owned();
+            assert_eq!(first, expected);
+            // the title column starts after the widest class, Firefox
+            let title = menu.shown.borrow()[0].lines().nth(1).unwrap().find("~/src");
+            assert_eq!(title, Some(7 + gap.parse::<usize>().unwrap()));
+        }
+    }
+
+    #[test]
+    fn huge_gaps_are_clamped() {
+        let menu = recorder("");
+        run_with(&["--gap", "100000"], &mut i3(), &menu).unwrap();
+        let first = menu.shown.borrow()[0].lines().next().unwrap().to_owned();
+        assert_eq!(first.find("Mozilla"), Some(7 + MAX_GAP));
+    }
+
+    #[test]
+    fn title_only_and_class_only_list_one_column() {
+        let out = run_with(&["--list", "--title-only"], &mut i3(), &answer("")).unwrap();
+        assert_eq!(out, "Mozilla Firefox\n~/src\nmain.rs\n");
+        let out = run_with(&["--list", "--class-only"], &mut i3(), &answer("")).unwrap();
+        assert_eq!(out, "Firefox\nURxvt\nEmacs\n");
+    }
+
+    #[test]
+    fn filters_see_titles_before_suffixes_are_stripped() {
+        let mut i3 = i3();
+        i3.windows[0].name = "Inbox - Mozilla Firefox".to_owned();
+        let args = ["--list", "--format", "{name}", "--smart-titles", "--filter-title", "Mozilla"];
+        let out = run_with(&args, &mut i3, &answer("")).unwrap();
+        assert_eq!(out, "Inbox\n");
+
+        let err = run_with(&["--strip-suffix", "("], &mut i3, &answer("")).unwrap_err();
+        assert!(err.to_string().starts_with("invalid --strip-suffix pattern '('"), "{}", err);
+    }
+
+    #[test]
+    fn current_output_keeps_the_focused_monitor_windows() {
+        let mut i3 = i3();
+        i3.workspaces[2].focused = true;
+        let menu = recorder("");
+        run_with(&["--format", "{name}", "--current-output"], &mut i3, &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["main.rs"]);
+    }
+
+    #[test]
+    fn current_workspace_keeps_its_windows() {
+        let mut i3 = i3();
+        i3.workspaces[0].focused = true;
+        i3.windows.push(window(4, "XTerm", "top", "2"));
+        let menu = recorder("");
+        run_with(&["--format", "{name}", "--current-workspace"], &mut i3, &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["Mozilla Firefox\n~/src"]);
+    }
+
+    #[test]
+    fn a_window_is_not_swapped_with_itself() {
+        let mut i3 = i3();
+        let menu = recorder("main.rs");
+        run_with(&["--swap", "--format", "{name}"], &mut i3, &menu).unwrap();
+        assert_eq!(menu.shown.borrow()[1], "Mozilla Firefox\n~/src");
+        assert!(i3.commands.is_empty());
+
+        run_with(&["--swap", "--format", "{name}"], &mut i3, &answer("~/src")).unwrap();
+        assert!(i3.commands.is_empty());
+    }
+
+    #[test]
+    fn no_focus_only_goes_with_fullscreen() {
+        let mut i3 = i3();
+        run_with(&["--fullscreen", "--no-focus", "--format", "{name}"], &mut i3, &answer("~/src"))
+            .unwrap();
+        assert_eq!(i3.commands, vec!["[id=\"2\"] fullscreen toggle"]);
+        assert!(run_with(&["--no-focus"], &mut i3, &answer("~/src")).is_err());
+    }
+
+    #[test]
+    fn align_right_pushes_classes_against_the_titles() {
+        let out = run_with(&["--list", "--align", "right", "--gap", "1"], &mut i3(), &answer(""))
+            .unwrap();
+        assert_eq!(out, "Firefox Mozilla Firefox\n  URxvt ~/src\n  Emacs main.rs\n");
+    }
+
+    #[test]
+    fn custom_keys_override_the_mode() {
+        let mut i3 = i3();
+        run_with(&["--format", "{name}"], &mut i3, &key("main.rs", 11)).unwrap();
+        run_with(&["--format", "{name}"], &mut i3, &key("~/src", 12)).unwrap();
+        run_with(&["--move", "--format", "{name}"], &mut i3, &key("main.rs", 10)).unwrap();
+        assert_eq!(i3.commands, vec!["[id=\"3\"] move workspace current; [id=\"3\"] focus",
+                                     "[id=\"2\"] kill", "[id=\"3\"] focus"]);
+    }
+
+    #[test]
+    fn escaped_menu_sends_nothing() {
+        let mut i3 = i3();
+        run_with(&["--format", "{name}"], &mut i3, &key("", 1)).unwrap();
+        // whatever text came with it, a failed menu is a cancelled one
+        run_with(&["--format", "{name}"], &mut i3, &key("main.rs", 1)).unwrap();
+        run_with(&["--workspace"], &mut i3, &key("mail", 1)).unwrap();
+        assert!(i3.commands.is_empty());
+    }
+
+    #[test]
+    fn numbered_entries_select_their_window() {
+        let out = run_with(&["--list", "--number", "--format", "{name}"], &mut i3(), &answer(""))
+            .unwrap();
+        assert_eq!(out, "1: Mozilla Firefox\n2: ~/src\n3: main.rs\n");
+
+        let mut i3 = i3();
+        run_with(&["--number", "--format", "{name}"], &mut i3, &answer("3: main.rs")).unwrap();
+        assert_eq!(i3.commands, vec!["[id=\"3\"] focus"]);
+    }
+
+    #[test]
+    fn workspace_picked_by_a_menu_program_is_switched_to() {
+        let mut i3 = i3();
+        run_with(&["--workspace"], &mut i3, &menu_script("web site")).unwrap();
+        assert_eq!(i3.commands, vec!["workspace \"web site\""]);
+    }
+
+    #[test]
+    fn window_picked_by_a_menu_program_is_moved_here() {
+        let mut i3 = i3();
+        let menu = menu_script("Emacs: main.rs");
+        run_with(&["--move", "--format", "{class}: {name}"], &mut i3, &menu).unwrap();
+        assert_eq!(i3.commands, vec!["[id=\"3\"] move workspace current"]);
+    }
+
+    #[test]
+    fn nothing_picked_by_a_menu_program_sends_nothing() {
+        let mut i3 = i3();
+        run_with(&["--move"], &mut i3, &menu_script("no such window")).unwrap();
+        assert!(i3.commands.is_empty());
+    }
+
+    #[test]
+    fn quiet_runs_print_nothing() {
+        let mut i3 = i3();
+        let out = run_with(&["--quiet", "--format", "{name}"], &mut i3, &answer("main.rs"));
+        assert_eq!(out.unwrap(), "");
+        assert_eq!(i3.commands, vec!["[id=\"3\"] focus"]);
+
+        let listed = run_with(&["--quiet", "--list", "--format", "{name}"], &mut i3, &answer(""));
+        assert_eq!(listed.unwrap(), "Mozilla Firefox\n~/src\nmain.rs\n");
+        assert!(run_with(&["--quiet", "--verbose"], &mut i3, &answer("")).is_err());
+    }
+
+    #[test]
+    fn move_prev_lists_the_focused_window_too() {
+        let mut i3 = i3();
+        i3.windows[1].focused = true;
+        let menu = recorder("~/src");
+        run_with(&["--move-prev", "--follow", "--format", "{name}"], &mut i3, &menu).unwrap();
+        assert_eq!(*menu.prompts.borrow(), vec![Some("move".to_owned())]);
+        assert!(menu.shown.borrow()[0].contains("~/src"));
+        assert_eq!(i3.commands,
+                   vec!["[id=\"2\"] move container to workspace prev; [id=\"2\"] focus"]);
+    }
+
+    #[test]
+    fn last_goes_back_without_a_menu() {
+        let mut i3 = i3();
+        let menu = recorder("");
+        run_with(&["--last"], &mut i3, &menu).unwrap();
+        assert!(menu.shown.borrow().is_empty());
+        assert_eq!(i3.commands, vec!["workspace back_and_forth"]);
+    }
+
+    #[test]
+    fn last_entry_is_not_a_workspace_called_like_it() {
+        let mut i3 = i3();
+        i3.workspaces.push(workspace("‹last›", -1, "eDP-1"));
+        let menu = recorder("‹last›");
+        run_with(&["--workspace"], &mut i3, &menu).unwrap();
+        assert!(menu.shown.borrow()[0].starts_with("‹last›\n"));
+        run_with(&["--workspace"], &mut i3, &answer("‹last› (2)")).unwrap();
+        assert_eq!(i3.commands, vec!["workspace back_and_forth", "workspace \"‹last›\""]);
+    }
+
+    #[test]
+    fn workspaces_show_their_window_count() {
+        let mut i3 = i3();
+        let menu = recorder("web site (1)");
+        run_with(&["--workspace", "--count-windows"], &mut i3, &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["‹last›\n1 (2)\n2 (0)\nweb site (1)"]);
+        assert_eq!(i3.commands, vec!["workspace \"web site\""]);
+    }
+
+    #[test]
+    fn workspace_filter_keeps_matching_names() {
+        let menu = recorder("");
+        run_with(&["--workspace", "--workspace-filter", "^\\d+$"], &mut i3(), &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["‹last›\n1\n2"]);
+
+        let err = run_with(&["--workspace", "--workspace-filter", "("], &mut i3(), &menu);
+        assert!(err.unwrap_err().to_string().starts_with("invalid --workspace-filter pattern"));
+    }
+
+    #[test]
+    fn markup_escapes_workspace_names() {
+        let mut i3 = i3();
+        i3.workspaces.push(workspace("R&D", -1, "eDP-1"));
+        let menu = recorder("R&amp;D");
+        run_with(&["--workspace", "--rofi", "--markup"], &mut i3, &menu).unwrap();
+        assert!(menu.shown.borrow()[0].contains("\nR&amp;D\n"));
+        assert_eq!(i3.commands, vec!["workspace \"R&D\""]);
+    }
+
+    #[test]
+    fn bring_moves_and_focuses_in_one_command() {
+        let mut i3 = i3();
+        run_with(&["--bring", "--format", "{name}"], &mut i3, &answer("main.rs")).unwrap();
+        assert_eq!(i3.commands, vec!["[id=\"3\"] move workspace current; [id=\"3\"] focus"]);
+    }
+
+    #[test]
+    fn commands_i3_rejects_are_errors() {
+        let mut i3 = MockConnection { error: Some("No such workspace".to_owned()), ..i3() };
+        let err = run_with(&["--workspace"], &mut i3, &answer("2")).unwrap_err();
+        assert_eq!(err.to_string(), "i3 could not run 'workspace \"2\"': No such workspace");
+    }
+
+    #[test]
+    fn single_fuzzy_match_is_acted_on_without_a_menu() {
+        let mut i3 = i3();
+        let menu = recorder("");
+        run_with(&["--fuzzy", "emc"], &mut i3, &menu).unwrap();
+        assert!(menu.shown.borrow().is_empty());
+        assert_eq!(i3.commands, vec!["[id=\"3\"] focus"]);
+
+        let menu = recorder("");
+        run_with(&["--fuzzy", "r", "--format", "{name}"], &mut i3, &menu).unwrap();
+        assert_eq!(menu.shown.borrow().len(), 1);
+    }
+
+    #[test]
+    fn rofi_script_selections_may_start_with_a_hyphen() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        let picked = separate_selection(args(&["quickswitch-i3", "--rofi-script", "- draft"]), 1);
+        assert_eq!(picked, args(&["quickswitch-i3", "--rofi-script", "--", "- draft"]));
+        let matches = app().get_matches_from_safe(picked).unwrap();
+        assert_eq!(matches.value_of("selection"), Some("- draft"));
+        // the first run only lists the entries, there is nothing to separate
+        let listing = args(&["quickswitch-i3", "--rofi-script", "--workspace"]);
+        assert_eq!(separate_selection(listing.clone(), 0), listing);
+
+        let mut i3 = i3();
+        i3.windows.push(window(4, "Gedit", "- draft", "2"));
+        let menu = RofiScriptBackend::new(1, Some("- draft".to_owned()), false, Vec::new());
+        run_with(&["--rofi-script", "--format", "{name}", "--", "- draft"], &mut i3, &menu)
+            .unwrap();
+        assert_eq!(i3.commands, vec!["[id=\"4\"] focus"]);
+    }
+
+    #[test]
+    fn combined_menu_lists_windows_then_workspaces() {
+        let mut i3 = i3();
+        let menu = recorder("2");
+        run_with(&["--menu", "--format", "{name}"], &mut i3, &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(),
+                   vec!["── Windows ──\nMozilla Firefox\n~/src\nmain.rs\n── Workspaces ──\n1\n2\n\
+                         web site"]);
+        run_with(&["--menu", "--format", "{name}"], &mut i3, &key("web site", 11)).unwrap();
+        run_with(&["--menu", "--format", "{name}"], &mut i3, &key("main.rs", 11)).unwrap();
+        assert_eq!(i3.commands, vec![
+            "workspace \"2\"",
+            "move container to workspace \"web site\"",
+            "[id=\"3\"] move workspace current; [id=\"3\"] focus",
+        ]);
+    }
+
+    #[test]
+    fn printed_selection_is_the_chosen_window() {
+        let mut i3 = i3();
+        let args = ["--print-selection", "--quiet", "--format", "{name}"];
+        let out = run_with(&args, &mut i3, &answer("main.rs")).unwrap();
+        assert_eq!(out, "3\tEmacs\tmain.rs\n");
+        assert_eq!(i3.commands, vec!["[id=\"3\"] focus"]);
+    }
+
+    #[test]
+    fn selection_only_prints_the_lone_urgent_window() {
+        let mut i3 = i3();
+        i3.windows[1].urgent = true;
+        let menu = recorder("");
+        let out = run_with(&["--urgent", "--selection-only"], &mut i3, &menu).unwrap();
+        assert_eq!(out, "[id=\"2\"]\n");
+        assert!(menu.shown.borrow().is_empty());
+        assert!(i3.commands.is_empty());
+    }
+
+    #[test]
+    fn ignored_classes_add_up_across_flags() {
+        let menu = recorder("");
+        let args = ["--format", "{name}", "--ignore-class", "Firefox, Emacs",
+                    "--ignore-class", "XTerm"];
+        let mut i3 = i3();
+        i3.windows.push(window(4, "XTerm", "top", "2"));
+        run_with(&args, &mut i3, &menu).unwrap();
+        assert_eq!(*menu.shown.borrow(), vec!["~/src"]);
+    }
+
+    #[test]
+    fn dry_run_kill_pid_prints_the_signal() {
+        let mut i3 = i3();
+        i3.windows[2].pid = Some(4242);
+        let args = ["--kill-pid", "--kill-signal", "term", "--dry-run", "--format", "{name}"];
+        let out = run_with(&args, &mut i3, &answer("main.rs")).unwrap();
+        assert_eq!(out, "kill -TERM 4242\n");
+        assert!(i3.commands.is_empty());
+    }
+
+    #[test]
+    fn rofi_beats_the_environment() {
+        let command = |args: &[&str]| {
+            let matches = app().get_matches_from(["quickswitch-i3"].iter().chain(args));
+            resolve_dmenu_command(flag_command(&matches, None), Some("dmenu -b"), Some("fuzzel"),
+                                  DEFAULT_DMENU_COMMAND)
+        };
+        assert_eq!(command(&["--rofi"]), DEFAULT_ROFI_COMMAND);
+        assert_eq!(command(&["--rofi", "--dmenu", "rofi -dmenu -i"]), "rofi -dmenu -i");
+        assert_eq!(command(&[]), "dmenu -b");
+    }
+
+    #[test]
+    fn dmenu_overrides_the_preset() {
+        let command = |args: &[&str]| {
+            let matches = app().get_matches_from(["quickswitch-i3"].iter().chain(args));
+            let preset = parse_value(&matches, "menu-preset").unwrap();
+            flag_command(&matches, preset).map(|command| command.to_owned())
+        };
+        assert_eq!(command(&["--menu-preset", "wofi"]), Some("wofi --dmenu -i".to_owned()));
+        assert_eq!(command(&["--menu-preset", "wofi", "--dmenu", "rofi -dmenu"]),
+                   Some("rofi -dmenu".to_owned()));
+        assert_eq!(command(&[]), None);
+    }
+
+    #[test]
+    fn renamed_workspaces_keep_their_spaces() {
+        let mut i3 = i3();
+        run_with(&["--rename"], &mut i3, &answer("web site")).unwrap();
+        assert_eq!(i3.commands, vec!["rename workspace \"web site\" to \"web site\""]);
+    }
+
+    #[test]
+    fn clear_urgency_focuses_the_urgent_window_again() {
+        let mut i3 = i3();
+        i3.windows[2].urgent = true;
+        run_with(&["--urgent", "--clear-urgency"], &mut i3, &answer("")).unwrap();
+        assert_eq!(i3.commands, vec!["[id=\"3\"] focus; [id=\"3\"] focus"]);
+        assert!(app().get_matches_from_safe(["quickswitch-i3", "--clear-urgency"]).is_err());
+    }
+}
@@ -0,0 +1,410 @@
+//! The part of i3's IPC protocol quickswitch-i3 talks, spoken by Sway too:
+//! a few requests, their JSON replies, and window events.
+//! See <https://i3wm.org/docs/ipc.html>.
+
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::process::Command;
+
+use serde_json;
+
+static MAGIC: &'static [u8] = b"i3-ipc";
+
+const RUN_COMMAND: u32 = 0;
+const GET_WORKSPACES: u32 = 1;
+const SUBSCRIBE: u32 = 2;
+const GET_OUTPUTS: u32 = 3;
+const GET_TREE: u32 = 4;
+const GET_MARKS: u32 = 5;
+/// Set on the type of event messages, the rest of it tells which event
+const EVENT_BIT: u32 = 1 << 31;
+const WINDOW_EVENT: u32 = 3;
+
+/// The replies quickswitch-i3 reads, with only the fields it reads
+pub mod reply {
+    /// The outcome of one of the commands of a `RUN_COMMAND`
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct CommandOutcome {
+        pub success: bool,
+        /// i3's message when it failed
+        #[serde(default)]
+        pub error: Option<String>,
+    }
+
+    /// The reply to a `RUN_COMMAND`, one outcome per command i3 parsed
+    #[derive(Debug, Clone)]
+    pub struct Command {
+        pub outcomes: Vec<CommandOutcome>,
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct Workspace {
+        /// -1 for a workspace without a number
+        pub num: i32,
+        pub name: String,
+        pub focused: bool,
+        pub urgent: bool,
+        pub output: String,
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct Output {
+        pub name: String,
+        /// Whether it has a mode, and so shows a workspace
+        pub active: bool,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum NodeType {
+        Root,
+        Output,
+        Con,
+        FloatingCon,
+        Workspace,
+        Dockarea,
+        /// Whatever a newer i3 may come up with
+        #[serde(other)]
+        Unknown,
+    }
+
+    /// A container of the layout tree
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(from = "RawNode")]
+    pub struct Node {
+        /// Ids of the children, most recently focused first
+        pub focus: Vec<i64>,
+        pub nodes: Vec<Node>,
+        pub floating_nodes: Vec<Node>,
+        /// The container id i3 addresses it with, `con_id`
+        pub id: i64,
+        /// The window title for a window
+        pub name: Option<String>,
+        pub nodetype: NodeType,
+        /// x, y, width and height, in display coordinates
+        pub rect: (i32, i32, i32, i32),
+        /// The X window id, none for a split container or a Wayland window
+        pub window: Option<i32>,
+        pub urgent: bool,
+        pub focused: bool,
+        /// WM_CLASS class and instance of an X window
+        pub class_name: Option<String>,
+        pub instance: Option<String>,
+        /// What Sway has instead for a native Wayland window
+        pub app_id: Option<String>,
+        pub marks: Vec<String>,
+        /// `_NET_WM_WINDOW_TYPE`, "normal", "dock", "popup_menu"...
+        pub window_type: Option<String>,
+        pub pid: Option<i32>,
+    }
+
+    #[derive(Deserialize)]
+    struct Rect {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    }
+
+    #[derive(Default, Deserialize)]
+    struct WindowProperties {
+        class: Option<String>,
+        instance: Option<String>,
+    }
+
+    // The node as i3 sends it, with the window properties nested
+    #[derive(Deserialize)]
+    struct RawNode {
+        #[serde(default)]
+        focus: Vec<i64>,
+        #[serde(default)]
+        nodes: Vec<Node>,
+        #[serde(default)]
+        floating_nodes: Vec<Node>,
+        id: i64,
+        name: Option<String>,
+        #[serde(rename = "type")]
+        nodetype: NodeType,
+        rect: Rect,
+        window: Option<i32>,
+        #[serde(default)]
+        urgent: bool,
+        #[serde(default)]
+        focused: bool,
+        window_properties: Option<WindowProperties>,
+        app_id: Option<String>,
+        #[serde(default)]
+        marks: Vec<String>,
+        window_type: Option<String>,
+        pid: Option<i32>,
+    }
+
+    impl From<RawNode> for Node {
+        fn from(raw: RawNode) -> Node {
+            let properties = raw.window_properties.unwrap_or_default();
+            Node {
+                focus: raw.focus,
+                nodes: raw.nodes,
+                floating_nodes: raw.floating_nodes,
+                id: raw.id,
+                name: raw.name,
+                nodetype: raw.nodetype,
+                rect: (raw.rect.x, raw.rect.y, raw.rect.width, raw.rect.height),
+                window: raw.window,
+                urgent: raw.urgent,
+                focused: raw.focused,
+                class_name: properties.class,
+                instance: properties.instance,
+                app_id: raw.app_id,
+                marks: raw.marks,
+                window_type: raw.window_type,
+                pid: raw.pid,
+            }
+        }
+    }
+}
+
+/// What happened to a window, other changes than these are `Other`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowChange {
+    Focus,
+    Close,
+    #[serde(other)]
+    Other,
+}
+
+/// A window event, the container being the window's
+#[derive(Debug, Clone, Deserialize)]
+pub struct WindowEvent {
+    pub change: WindowChange,
+    pub container: reply::Node,
+}
+
+#[derive(Deserialize)]
+struct Subscribed {
+    success: bool,
+}
+
+fn invalid_data<E: Into<Box<dyn Error + Send + Sync>>>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn send_message(stream: &mut UnixStream, message_type: u32, payload: &str) -> io::Result<()> {
+    let mut message = Vec::with_capacity(MAGIC.len() + 8 + payload.len());
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    message.extend_from_slice(&message_type.to_le_bytes());
+    message.extend_from_slice(payload.as_bytes());
+    stream.write_all(&message)
+}
+
+// The type and payload of the next message
+fn receive_message(stream: &mut UnixStream) -> io::Result<(u32, Vec<u8>)> {
+    let mut header = [0; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..6] != MAGIC {
+        return Err(invalid_data("not an i3 IPC message"));
+    }
+    let mut word = [0; 4];
+    word.copy_from_slice(&header[6..10]);
+    let length = u32::from_le_bytes(word);
+    word.copy_from_slice(&header[10..]);
+    let message_type = u32::from_le_bytes(word);
+
+    let mut payload = vec![0; length as usize];
+    stream.read_exact(&mut payload)?;
+    Ok((message_type, payload))
+}
+
+fn request<T>(stream: &mut UnixStream, message_type: u32, payload: &str) -> io::Result<T>
+    where T: ::serde::de::DeserializeOwned
+{
+    send_message(stream, message_type, payload)?;
+    let (reply_type, reply) = receive_message(stream)?;
+    if reply_type != message_type {
+        return Err(invalid_data(format!("expected a reply of type {}, got {}",
+                                        message_type, reply_type)));
+    }
+    serde_json::from_slice(&reply).map_err(invalid_data)
+}
+
+/// Where the running i3 listens, as `i3 --get-socketpath` prints it
+pub fn default_socket_path() -> io::Result<String> {
+    let output = Command::new("i3").arg("--get-socketpath").output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("i3 --get-socketpath failed"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end().to_owned())
+}
+
+/// A connection asking i3 for things and running commands
+#[derive(Debug)]
+pub struct I3Connection {
+    stream: UnixStream,
+}
+
+impl I3Connection {
+    /// Connects to the socket of the running i3, see `default_socket_path`
+    pub fn connect() -> io::Result<I3Connection> {
+        I3Connection::connect_to(&default_socket_path()?)
+    }
+
+    pub fn connect_to(path: &str) -> io::Result<I3Connection> {
+        Ok(I3Connection { stream: UnixStream::connect(path)? })
+    }
+
+    pub fn command(&mut self, command: &str) -> io::Result<reply::Command> {
+        let outcomes = request(&mut self.stream, RUN_COMMAND, command)?;
+        Ok(reply::Command { outcomes: outcomes })
+    }
+
+    pub fn get_workspaces(&mut self) -> io::Result<Vec<reply::Workspace>> {
+        request(&mut self.stream, GET_WORKSPACES, "")
+    }
+
+    pub fn get_outputs(&mut self) -> io::Result<Vec<reply::Output>> {
+        request(&mut self.stream, GET_OUTPUTS, "")
+    }
+
+    pub fn get_tree(&mut self) -> io::Result<reply::Node> {
+        request(&mut self.stream, GET_TREE, "")
+    }
+
+    pub fn get_marks(&mut self) -> io::Result<Vec<String>> {
+        request(&mut self.stream, GET_MARKS, "")
+    }
+}
+
+/// A connection subscribed to window events
+#[derive(Debug)]
+pub struct I3EventListener {
+    stream: UnixStream,
+}
+
+impl I3EventListener {
+    /// Connects to the socket of the running i3, see `default_socket_path`
+    pub fn connect() -> io::Result<I3EventListener> {
+        I3EventListener::connect_to(&default_socket_path()?)
+    }
+
+    pub fn connect_to(path: &str) -> io::Result<I3EventListener> {
+        let mut stream = UnixStream::connect(path)?;
+        let subscribed: Subscribed = request(&mut stream, SUBSCRIBE, "[\"window\"]")?;
+        if !subscribed.success {
+            return Err(io::Error::other("i3 refused the subscription"));
+        }
+        Ok(I3EventListener { stream: stream })
+    }
+
+    /// The next window event, waiting for it; an error once i3 went away
+    pub fn next_event(&mut self) -> io::Result<WindowEvent> {
+        loop {
+            let (message_type, payload) = receive_message(&mut self.stream)?;
+            // i3 sends the subscribed events only, but a reply could be late
+            if message_type == EVENT_BIT | WINDOW_EVENT {
+                return serde_json::from_slice(&payload).map_err(invalid_data);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::process;
+    use std::thread;
+
+    // A socket path nobody else uses
+    fn socket(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("quickswitch-i3-ipc-{}-{}", process::id(), name))
+    }
+
+    // Answers each request with the reply of the same index, checking its
+    // type; events are sent when their turn comes. Returns the payloads.
+    fn serve(path: &PathBuf, replies: Vec<(u32, &'static str)>) -> thread::JoinHandle<Vec<String>> {
+        let _ = ::std::fs::remove_file(path);
+        let listener = UnixListener::bind(path).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut payloads = Vec::new();
+            for (message_type, reply) in replies {
+                if message_type & EVENT_BIT == 0 {
+                    let (received, payload) = receive_message(&mut stream).unwrap();
+                    assert_eq!(received, message_type);
+                    payloads.push(String::from_utf8(payload).unwrap());
+                }
+                send_message(&mut stream, message_type, reply).unwrap();
+            }
+            payloads
+        })
+    }
+
+    #[test]
+    fn requests_are_answered_with_their_replies() {
+        let path = socket("requests");
+        let server = serve(&path, vec![
+            (RUN_COMMAND, r#"[{"success":true},{"success":false,"error":"No such workspace"}]"#),
+            (GET_WORKSPACES, r#"[{"num":2,"name":"2: mail","visible":true,"focused":true,
+                                 "urgent":false,"output":"eDP-1","rect":{}}]"#),
+            (GET_MARKS, r#"["a","b"]"#),
+        ]);
+        let mut conn = I3Connection::connect_to(path.to_str().unwrap()).unwrap();
+
+        let outcomes = conn.command("focus; workspace 9").unwrap().outcomes;
+        assert!(outcomes[0].success);
+        assert_eq!(outcomes[1].error, Some("No such workspace".to_owned()));
+        let workspace = &conn.get_workspaces().unwrap()[0];
+        assert_eq!((workspace.num, workspace.name.as_str()), (2, "2: mail"));
+        assert_eq!(conn.get_marks().unwrap(), vec!["a", "b"]);
+
+        assert_eq!(server.join().unwrap(), vec!["focus; workspace 9", "", ""]);
+        let _ = ::std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn nodes_take_class_and_instance_from_the_window_properties() {
+        let node: reply::Node = serde_json::from_str(r#"{
+            "id": 94, "name": "~", "type": "con", "border": "pixel", "layout": "splith",
+            "rect": {"x": 0, "y": 20, "width": 800, "height": 600},
+            "window": 8388621, "urgent": false, "focused": true, "marks": ["m"],
+            "window_properties": {"class": "URxvt", "instance": "urxvt", "title": "~"},
+            "nodes": [], "floating_nodes": [], "focus": []
+        }"#).unwrap();
+        assert_eq!(node.nodetype, reply::NodeType::Con);
+        assert_eq!(node.rect, (0, 20, 800, 600));
+        assert_eq!(node.class_name, Some("URxvt".to_owned()));
+        assert_eq!(node.instance, Some("urxvt".to_owned()));
+        assert_eq!(node.app_id, None);
+
+        let wayland: reply::Node = serde_json::from_str(r#"{
+            "id": 5, "name": "foot", "type": "con", "window": null,
+            "rect": {"x": 0, "y": 0, "width": 1, "height": 1},
+            "app_id": "foot", "pid": 4242, "shell": "xdg_shell"
+        }"#).unwrap();
+        assert_eq!(wayland.window, None);
+        assert_eq!(wayland.app_id, Some("foot".to_owned()));
+        assert_eq!(wayland.pid, Some(4242));
+    }
+
+    #[test]
+    fn window_events_are_read_once_subscribed() {
+        let path = socket("events");
+        let server = serve(&path, vec![
+            (SUBSCRIBE, r#"{"success":true}"#),
+            (EVENT_BIT | WINDOW_EVENT, r#"{"change":"focus","container":{"id":7,"name":"~",
+                "type":"con","rect":{"x":0,"y":0,"width":1,"height":1},"window":3}}"#),
+        ]);
+        let mut listener = I3EventListener::connect_to(path.to_str().unwrap()).unwrap();
+        let event = listener.next_event().unwrap();
+        assert_eq!((event.change, event.container.id), (WindowChange::Focus, 7));
+
+        assert_eq!(server.join().unwrap(), vec!["[\"window\"]"]);
+        assert!(listener.next_event().is_err());
+        let _ = ::std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,3837 @@
+//! Window and workspace listing for i3 (and Sway), and the plumbing to pick
+//! one of them through dmenu-like menus.
+
+// written for Rust 2015: `field: field` and `&'static` throughout
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitStatus, Stdio};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub mod cli;
+pub mod ipc;
+use ipc::{I3Connection, I3EventListener, WindowChange, WindowEvent};
+use ipc::reply;
+
+extern crate clap;
+
+extern crate libc;
+
+extern crate regex;
+use regex::Regex;
+
+extern crate unicode_width;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+
+
+/// Window names hidden unless defaults are disabled
+static IGNORE_WINDOW_NAME: [&'static str; 1] = ["__i3_scratch"];
+/// Name of the internal output holding the scratchpad workspace
+pub static SCRATCHPAD_OUTPUT: &'static str = "__i3";
+/// Window classes hidden unless defaults are disabled
+static IGNORE_WINDOW_CLASS: [&'static str; 1] = ["i3bar"];
+/// Window types of docks, panels and transient popups, hidden unless
+/// `--include-docks` is given
+static IGNORE_WINDOW_TYPES: [&'static str; 8] = [
+    "dock", "toolbar", "splash", "menu", "dropdown_menu", "popup_menu", "tooltip", "notification",
+];
+
+/// Application names trailing the titles of common programs, stripped by
+/// `--smart-titles`
+pub static SMART_TITLE_SUFFIXES: [&'static str; 4] = [
+    r"\s+[-—–]\s+Mozilla Firefox$",
+    r"\s+[-—–]\s+Chromium$",
+    r"\s+[-—–]\s+Google Chrome$",
+    r"\s+[-—–]\s+Visual Studio Code$",
+];
+
+/// Marker of floating windows, for `--show-floating` and `{floating}`
+pub static FLOATING_MARKER: &'static str = "⬚";
+/// Label of the entry going back to the previous workspace
+pub static LAST_WORKSPACE_LABEL: &'static str = "‹last›";
+
+/// Environment variables holding a menu command, in order of preference
+pub static DMENU_ENV_VARS: [&'static str; 2] = ["QUICKSWITCH_DMENU", "DMENU"];
+/// Menu used when neither the command line nor the config file set one
+pub static DEFAULT_DMENU_COMMAND: &'static str = "dmenu -b -i -l 20";
+/// Menu used with --rofi when nothing else sets one
+pub static DEFAULT_ROFI_COMMAND: &'static str = "rofi -dmenu -i";
+/// Option of dmenu, rofi and most of their clones taking the number of lines
+pub static LINES_FLAG: &'static str = "-l";
+/// rofi prints the selected row index then its text; free text is index -1
+pub static ROFI_FORMAT: &'static str = "-format 'i s'";
+
+/// Spaces between the columns of the window list, and the most allowed
+pub static DEFAULT_GAP: usize = 5;
+pub static MAX_GAP: usize = 40;
+
+/// Selections remembered per mode
+pub static HISTORY_SIZE: usize = 10;
+
+/// Container ids tracked by the daemon
+pub static MRU_SIZE: usize = 200;
+
+/// Connection attempts after the first one fails
+pub static CONNECT_RETRIES: u32 = 3;
+/// Most attempts --connect-retries is allowed, more are clamped to it
+pub static MAX_CONNECT_RETRIES: u32 = 20;
+/// Pause before the first retry, doubled for each of the next ones
+static CONNECT_RETRY_DELAY_MS: u64 = 200;
+/// Longest pause between two attempts, however many came before
+static MAX_CONNECT_RETRY_DELAY_MS: u64 = 5000;
+
+/// Contents of `config.toml`, every key is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub dmenu_command: Option<String>,
+    pub ignore_names: Vec<String>,
+    pub ignore_classes: Vec<String>,
+    /// Display names of classes, keyed by class or by regex
+    pub class_aliases: BTreeMap<String, String>,
+}
+
+/// Friendlier names shown instead of some window classes
+#[derive(Debug, Default)]
+pub struct ClassAliases {
+    exact: HashMap<String, String>,
+    patterns: Vec<(Regex, String)>,
+}
+
+/// Window names and classes left out of the list
+#[derive(Debug)]
+pub struct Ignores {
+    names: Vec<String>,
+    classes: Vec<String>,
+    types: Vec<String>,
+    /// Title given to windows without a name, which are left out without one
+    unnamed: Option<String>,
+}
+
+/// Regexes a window must match to be listed
+#[derive(Debug, Default)]
+pub struct WindowFilter {
+    pub any: Option<Regex>,
+    pub title: Option<Regex>,
+    pub class: Option<Regex>,
+}
+
+/// A managed X (or Wayland) window
+#[derive(Debug, Clone, Serialize)]
+pub struct Window {
+    /// X window id, native Wayland clients have none
+    pub id: Option<i32>,
+    /// Container id, which i3 and Sway give every window
+    pub con_id: i64,
+    pub name: String,
+    #[serde(rename = "class")]
+    pub class_name: Option<String>,
+    pub instance: Option<String>,
+    pub focused: bool,
+    pub urgent: bool,
+    pub floating: bool,
+    pub workspace: Option<String>,
+    pub output: Option<String>,
+    pub marks: Vec<String>,
+    /// Position and size, `(x, y, width, height)`
+    pub rect: (i32, i32, i32, i32),
+    /// Process of the client, when i3 knows it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i32>,
+    /// Icon name for rofi, looked up in the desktop entries on demand
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+}
+
+/// Where a node sits in the tree, filled in while walking down to it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Location<'a> {
+    pub workspace: Option<&'a str>,
+    pub output: Option<&'a str>,
+    /// Below i3's hidden `__i3` output, which holds the scratchpad
+    pub scratchpad: bool,
+    /// Below a floating container rather than in the tiling tree
+    pub floating: bool,
+}
+
+/// Order of the window list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Mru,
+    Alpha,
+    Class,
+}
+
+/// Order of the workspace list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkspaceOrder {
+    Num,
+    Name,
+    Output,
+}
+
+/// Signal `--kill-pid` sends: TERM lets the client clean up, KILL is for one
+/// too stuck to even do that
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KillSignal {
+    Term,
+    Kill,
+}
+
+/// A menu program started with the flags it needs to read options on stdin
+/// and print the selection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuPreset {
+    Dmenu,
+    Bemenu,
+    Wofi,
+    Fuzzel,
+}
+
+/// What window entries show, besides the markers
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Columns {
+    #[default]
+    All,
+    TitleOnly,
+    ClassOnly,
+}
+
+/// Side of its column the class is pushed to
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Right,
+}
+
+/// How window entries are rendered in the menu
+#[derive(Debug, Default)]
+pub struct Layout {
+    pub padding: usize,
+    pub max_title_width: Option<usize>,
+    pub marker_focused: Option<String>,
+    pub marker_urgent: Option<String>,
+    pub marker_floating: Option<String>,
+    pub workspace_padding: Option<usize>,
+    pub instance_padding: Option<usize>,
+    pub marks_padding: Option<usize>,
+    pub pid_padding: Option<usize>,
+    /// Width the titles are padded to, the geometry following them
+    pub title_padding: Option<usize>,
+    pub columns: Columns,
+    pub align: Align,
+    /// Color of the class column in pango markup, for rofi's `-markup-rows`;
+    /// everything else gets escaped
+    pub markup_color: Option<String>,
+    /// Part of `padding` separating the class from the next column
+    pub gap: usize,
+    /// Joins the columns instead of padding them
+    pub separator: Option<String>,
+    pub template: Option<Template>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Text(String),
+    Class,
+    Name,
+    Id,
+    ConId,
+    Workspace,
+    Mark,
+    Instance,
+    Pid,
+    Floating,
+}
+
+/// A user supplied `--format`, parsed up front so that unknown placeholders
+/// are reported before anything is displayed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    tokens: Vec<Token>,
+}
+
+/// An i3 workspace
+#[derive(Debug, Clone, Serialize)]
+pub struct Workspace {
+    pub name: String,
+    pub num: i32,
+    pub output: String,
+    pub focused: bool,
+    pub urgent: bool,
+    /// Windows on the workspace, only counted on demand
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_count: Option<usize>,
+}
+
+/// An i3 mark, set on a container with `mark`
+#[derive(Debug, Clone, Serialize)]
+pub struct Mark {
+    pub name: String,
+}
+
+/// The previously focused workspace, whatever its name
+#[derive(Debug)]
+pub struct LastWorkspace;
+
+/// An active output (monitor)
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub name: String,
+}
+
+/// Anything that can be picked in the menu and used in an i3 command
+pub trait Selectable {
+    /// What i3 commands refer to this by, criteria or name
+    fn to_select_string(&self) -> String;
+
+    /// The window behind the entry, for things only windows have
+    fn as_window(&self) -> Option<&Window> {
+        None
+    }
+
+    /// The workspace behind the entry
+    fn as_workspace(&self) -> Option<&Workspace> {
+        None
+    }
+}
+
+impl Selectable for Window {
+    fn to_select_string(&self) -> String {
+        match self.id {
+            Some(id) => format!("[id=\"{}\"]", id),
+            None => format!("[con_id=\"{}\"]", self.con_id),
+        }
+    }
+
+    fn as_window(&self) -> Option<&Window> {
+        Some(self)
+    }
+}
+
+impl Selectable for Workspace {
+    fn to_select_string(&self) -> String {
+        self.name.to_owned()
+    }
+
+    fn as_workspace(&self) -> Option<&Workspace> {
+        Some(self)
+    }
+}
+
+impl Selectable for Mark {
+    // criteria are regexes, match the mark as is and nothing more
+    fn to_select_string(&self) -> String {
+        format!("[con_mark={}]", quote_arg(&format!("^{}$", regex::escape(&self.name))))
+    }
+}
+
+impl Selectable for LastWorkspace {
+    fn to_select_string(&self) -> String {
+        "back_and_forth".to_owned()
+    }
+}
+
+impl Selectable for Output {
+    fn to_select_string(&self) -> String {
+        self.name.to_owned()
+    }
+}
+
+impl Window {
+    /// Menu line for this window, using the template if there is one
+    pub fn label(&self, layout: &Layout) -> String {
+        match layout.template {
+            // a title with a `&` would break rofi's markup otherwise
+            Some(ref template) if layout.markup_color.is_some() => {
+                escape_markup(&template.render_cut(self, layout.max_title_width))
+            }
+            Some(ref template) => template.render_cut(self, layout.max_title_width),
+            None => self.pad_format(layout),
+        }
+    }
+
+    /// Default rendering: aligned columns followed by the title, and by the
+    /// geometry if there is a title padding
+    pub fn pad_format(&self, layout: &Layout) -> String {
+        // escaped once cut and padded, entities don't take up any room
+        let markup = |s: String| {
+            if layout.markup_color.is_some() { escape_markup(&s) } else { s }
+        };
+
+        let title = match layout.max_title_width {
+            Some(width) => truncate(&self.name, width),
+            None => self.name.to_owned(),
+        };
+        let name = markup(title.to_owned());
+        let markers = markup(self.markers(layout));
+        let class = self.class_name.as_ref().map_or("", |s| s.as_str());
+
+        // a single column has nothing to be aligned with
+        match layout.columns {
+            Columns::TitleOnly => return format!("{}{}", markers, name),
+            Columns::ClassOnly => return format!("{}{}", markers, markup(class.to_owned())),
+            Columns::All => (),
+        }
+
+        let colored = |class: String| match layout.markup_color {
+            Some(ref color) => {
+                format!("<span foreground=\"{}\">{}</span>", color, escape_markup(&class))
+            }
+            None => class,
+        };
+        let marks = self.marks.join(",");
+        let pid = self.pid.map(|p| p.to_string()).unwrap_or_default();
+        // the optional columns, shown when they have a width
+        let before_class = [
+            (layout.workspace_padding, self.workspace.as_ref().map_or("", |s| s.as_str())),
+            (layout.marks_padding, marks.as_str()),
+            (layout.pid_padding, pid.as_str()),
+        ];
+        let instance = self.instance.as_ref().map_or("", |s| s.as_str());
+        let instance = (layout.instance_padding, instance);
+
+        // a separator stands in for the padding, nothing gets aligned
+        if let Some(ref separator) = layout.separator {
+            let mut columns = before_class.iter()
+                .filter(|c| c.0.is_some())
+                .map(|c| markup(c.1.to_owned()))
+                .collect::<Vec<_>>();
+            columns.push(colored(class.to_owned()));
+            if instance.0.is_some() {
+                columns.push(markup(instance.1.to_owned()));
+            }
+            columns.push(name);
+            if layout.title_padding.is_some() {
+                columns.push(markup(self.geometry()));
+            }
+            let separator = markup(separator.to_owned());
+            return format!("{}{}", markers, columns.join(separator.as_str()));
+        }
+
+        let padded = |&(width, value): &(Option<usize>, &str)| {
+            markup(width.map(|width| pad(value, width)).unwrap_or_default())
+        };
+        let class = match layout.align {
+            Align::Left => pad(class, layout.padding),
+            Align::Right => {
+                let width = layout.padding.saturating_sub(layout.gap);
+                format!("{}{}", pad_left(class, width), " ".repeat(layout.padding - width))
+            }
+        };
+
+        let mut columns = vec![markers];
+        columns.extend(before_class.iter().map(&padded));
+        columns.push(colored(class));
+        columns.push(padded(&instance));
+        match layout.title_padding {
+            Some(width) => {
+                columns.push(markup(pad(&title, width)));
+                columns.push(markup(self.geometry()));
+            }
+            None => columns.push(name),
+        }
+        // an empty title would leave the padding dangling
+        columns.concat().trim_end().to_owned()
+    }
+
+    /// Size and position the way X geometries are written, `800x600+10+20`
+    pub fn geometry(&self) -> String {
+        let (x, y, width, height) = self.rect;
+        format!("{}x{}{:+}{:+}", width, height, x, y)
+    }
+
+    // Each enabled marker gets a fixed-width slot so columns stay aligned
+    fn markers(&self, layout: &Layout) -> String {
+        let mut prefix = String::new();
+        let slots = [(layout.marker_focused.as_ref(), self.focused),
+                     (layout.marker_urgent.as_ref(), self.urgent),
+                     (layout.marker_floating.as_ref(), self.floating)];
+        for &(marker, set) in &slots {
+            if let Some(marker) = marker {
+                prefix.push_str(&pad(if set { marker.as_str() } else { "" }, marker.width()));
+            }
+        }
+
+        if !prefix.is_empty() {
+            prefix.push(' ');
+        }
+        prefix
+    }
+}
+
+impl std::str::FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Template, String> {
+        let mut tokens = Vec::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                tokens.push(Token::Text(rest[..start].to_owned()));
+            }
+            let end = rest[start..].find('}')
+                .ok_or_else(|| format!("unclosed placeholder in format '{}'", s))? + start;
+            tokens.push(match &rest[start + 1..end] {
+                "class" => Token::Class,
+                "name" => Token::Name,
+                "id" => Token::Id,
+                "con_id" => Token::ConId,
+                "workspace" => Token::Workspace,
+                "mark" => Token::Mark,
+                "instance" => Token::Instance,
+                "pid" => Token::Pid,
+                "floating" => Token::Floating,
+                other => return Err(format!("unknown placeholder '{{{}}}' in format '{}'", other, s)),
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            tokens.push(Token::Text(rest.to_owned()));
+        }
+
+        Ok(Template { tokens: tokens })
+    }
+}
+
+impl Template {
+    /// Whether anything gets substituted, a template without placeholders
+    /// does the same thing whatever is selected
+    pub fn has_placeholders(&self) -> bool {
+        self.tokens.iter().any(|t| !matches!(*t, Token::Text(_)))
+    }
+
+    /// Substitutes the placeholders with the window's values
+    pub fn render(&self, window: &Window) -> String {
+        self.render_cut(window, None)
+    }
+
+    // Menu lines get the title cut like the padded columns, commands don't
+    fn render_cut(&self, window: &Window, max_title_width: Option<usize>) -> String {
+        self.tokens.iter().map(|t| match *t {
+            Token::Text(ref text) => text.to_owned(),
+            Token::Class => window.class_name.to_owned().unwrap_or_default(),
+            Token::Name => match max_title_width {
+                Some(width) => truncate(&window.name, width),
+                None => window.name.to_owned(),
+            },
+            Token::Id => window.id.map(|id| id.to_string()).unwrap_or_default(),
+            Token::ConId => window.con_id.to_string(),
+            Token::Workspace => window.workspace.to_owned().unwrap_or_default(),
+            Token::Mark => window.marks.join(","),
+            Token::Instance => window.instance.to_owned().unwrap_or_default(),
+            Token::Pid => window.pid.map(|p| p.to_string()).unwrap_or_default(),
+            Token::Floating => {
+                if window.floating { FLOATING_MARKER.to_owned() } else { String::new() }
+            }
+        }).collect()
+    }
+}
+
+/// Cuts from title the part matched by any of patterns, as long as it ends
+/// the title. A title made only of a suffix is kept whole.
+pub fn strip_suffixes(title: &str, patterns: &[Regex]) -> String {
+    let mut stripped = title;
+    for re in patterns {
+        if let Some(m) = re.find_iter(stripped).find(|m| m.end() == stripped.len()) {
+            stripped = &stripped[..m.start()];
+        }
+    }
+
+    let kept = if stripped.is_empty() { title } else { stripped };
+    kept.to_owned()
+}
+
+// `{: <N}` pads by chars, not display columns, so pad by hand
+fn pad(s: &str, width: usize) -> String {
+    format!("{}{}", s, " ".repeat(width.saturating_sub(s.width())))
+}
+
+/// Escapes the characters pango markup gives a meaning to
+pub fn escape_markup(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn pad_left(s: &str, width: usize) -> String {
+    format!("{}{}", " ".repeat(width.saturating_sub(s.width())), s)
+}
+
+// Cuts s to at most width display columns, marking the cut with an ellipsis;
+// no room at all leaves nothing, not even the ellipsis
+fn truncate(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_owned();
+    }
+    if width == 0 {
+        return String::new();
+    }
+
+    let mut truncated = String::new();
+    let mut used = 0;
+    for ch in s.chars() {
+        let w = ch.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        used += w;
+        truncated.push(ch);
+    }
+    truncated.push('…');
+    truncated
+}
+
+impl Ignores {
+    /// Starts from the built-in ignores, or from nothing; window types are
+    /// ignored either way
+    pub fn new(use_defaults: bool) -> Ignores {
+        let types = IGNORE_WINDOW_TYPES.iter().map(|s| s.to_string()).collect();
+        if use_defaults {
+            Ignores {
+                names: IGNORE_WINDOW_NAME.iter().map(|s| s.to_string()).collect(),
+                classes: IGNORE_WINDOW_CLASS.iter().map(|s| s.to_string()).collect(),
+                types: types,
+                unnamed: None,
+            }
+        } else {
+            Ignores { names: Vec::new(), classes: Vec::new(), types: types, unnamed: None }
+        }
+    }
+
+    /// Lists windows without a name too, titled placeholder
+    pub fn include_unnamed(&mut self, placeholder: &str) {
+        self.unnamed = Some(placeholder.to_owned());
+    }
+
+    /// Lists docks and popups along with the normal windows
+    pub fn include_docks(&mut self) {
+        self.types.clear();
+    }
+
+    /// Hides these names and classes as well, each entry is kept once
+    pub fn extend(&mut self, names: &[String], classes: &[String]) {
+        for name in names {
+            push_new(&mut self.names, name);
+        }
+        for class in classes {
+            push_new(&mut self.classes, class);
+        }
+    }
+}
+
+fn push_new(list: &mut Vec<String>, value: &str) {
+    if !list.iter().any(|v| v == value) {
+        list.push(value.to_owned());
+    }
+}
+
+/// Command line values, each of which can be a comma separated list:
+/// `a,b` and `c` give a, b and c; blanks around the commas are ignored
+pub fn split_list(values: &[String]) -> Vec<String> {
+    values.iter()
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_owned())
+        .collect()
+}
+
+impl ClassAliases {
+    /// Every key is tried as an exact class, and also as a regex matching the
+    /// whole class if it is a valid one: `Gimp` doesn't alias `Gimp-2.10`,
+    /// `Gimp.*` does
+    pub fn new(aliases: &BTreeMap<String, String>) -> ClassAliases {
+        ClassAliases {
+            exact: aliases.iter().map(|(k, v)| (k.to_owned(), v.to_owned())).collect(),
+            patterns: aliases.iter()
+                .filter_map(|(k, v)| {
+                    Regex::new(&format!("^(?:{})$", k)).ok().map(|re| (re, v.to_owned()))
+                })
+                .collect(),
+        }
+    }
+
+    /// The alias of class, an exact key first, then the first matching
+    /// pattern in key order
+    pub fn resolve(&self, class: &str) -> Option<&str> {
+        self.exact.get(class)
+            .or_else(|| {
+                self.patterns.iter()
+                    .find(|&(re, _)| re.is_match(class))
+                    .map(|(_, alias)| alias)
+            })
+            .map(|s| s.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exact.is_empty()
+    }
+}
+
+impl WindowFilter {
+    /// Whether window passes every filter that is set
+    pub fn matches(&self, window: &Window) -> bool {
+        let class = window.class_name.as_ref().map_or("", |s| s.as_str());
+
+        self.any.as_ref().is_none_or(|re| re.is_match(&window.name) || re.is_match(class)) &&
+        self.title.as_ref().is_none_or(|re| re.is_match(&window.name)) &&
+        self.class.as_ref().is_none_or(|re| re.is_match(class))
+    }
+}
+
+/// `$XDG_CONFIG_HOME/quickswitch-i3`, `~/.config` if unset
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("quickswitch-i3"))
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// History file of a mode in dir, `windows` or `workspaces`
+pub fn history_path(dir: &Path, mode: &str) -> PathBuf {
+    dir.join(format!("history-{}", mode))
+}
+
+/// Past selections, most recent first; a missing file is an empty history
+pub fn load_history(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    use std::io::prelude::*;
+
+    let mut content = String::new();
+    match File::open(path).and_then(|mut file| file.read_to_string(&mut content)) {
+        Ok(_) => Ok(content.lines().filter(|l| !l.is_empty()).map(|l| l.to_owned()).collect()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("could not read history file {}: {}", path.display(), e).into()),
+    }
+}
+
+/// State file shared between the daemon and one-shot runs, in
+/// `$XDG_RUNTIME_DIR` if set, the config directory otherwise
+pub fn mru_path() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(|dir| PathBuf::from(dir).join("quickswitch-i3-mru"))
+        .or_else(|| config_dir().map(|dir| dir.join("mru")))
+}
+
+/// Container ids of the state file, empty if there is none
+pub fn load_mru(path: &Path) -> Result<Vec<i64>, Box<dyn Error>> {
+    Ok(load_history(path)?.iter().filter_map(|l| l.parse().ok()).collect())
+}
+
+// written aside then renamed, so readers never see a partial file
+fn save_mru(path: &Path, mru: &[i64]) -> Result<(), Box<dyn Error>> {
+    let tmp = path.with_extension("tmp");
+    save_history(&tmp, &mru.iter().map(|id| id.to_string()).collect::<Vec<_>>())?;
+    fs::rename(&tmp, path)
+        .map_err(|e| format!("could not write state file {}: {}", path.display(), e).into())
+}
+
+pub fn save_history(path: &Path, history: &[String]) -> Result<(), Box<dyn Error>> {
+    use std::io::prelude::*;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+    }
+    let mut file = File::create(path)
+        .map_err(|e| format!("could not write history file {}: {}", path.display(), e))?;
+    for entry in history {
+        writeln!(file, "{}", entry)
+            .map_err(|e| format!("could not write history file {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Moves entry to the front of history, keeping at most size entries
+pub fn record_history(history: &mut Vec<String>, entry: &str, size: usize) {
+    history.retain(|h| h != entry);
+    history.insert(0, entry.to_owned());
+    history.truncate(size);
+}
+
+/// What is used of an application's desktop file
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesktopEntry {
+    pub name: Option<String>,
+    pub icon: Option<String>,
+}
+
+/// The desktop entries installed, read once per run
+#[derive(Debug, Default)]
+pub struct DesktopEntries {
+    entries: HashMap<String, DesktopEntry>,
+}
+
+/// Directories holding `.desktop` files, most important first:
+/// `$XDG_DATA_HOME` then `$XDG_DATA_DIRS`
+pub fn application_dirs() -> Vec<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+    let data_dirs = env::var("XDG_DATA_DIRS").ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_owned());
+
+    data_home.into_iter()
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+// the value of key in the [Desktop Entry] group of a desktop file
+fn desktop_value(content: &str, key: &str) -> Option<String> {
+    let mut in_entry = false;
+    for line in content.lines().map(|l| l.trim()) {
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+        } else if in_entry {
+            let mut parts = line.splitn(2, '=');
+            if parts.next().map(|k| k.trim()) == Some(key) {
+                return parts.next().map(|v| v.trim().to_owned()).filter(|v| !v.is_empty());
+            }
+        }
+    }
+    None
+}
+
+impl DesktopEntries {
+    /// Reads every desktop file of dirs. Entries are known by their desktop
+    /// id and by their `StartupWMClass`, both lowercased; when two files
+    /// claim the same key the one from the earlier directory is kept.
+    pub fn scan(dirs: &[PathBuf]) -> DesktopEntries {
+        let mut entries = HashMap::new();
+        for dir in dirs {
+            let mut files = match fs::read_dir(dir) {
+                Ok(files) => files.filter_map(|f| f.ok()).map(|f| f.path()).collect::<Vec<_>>(),
+                Err(_) => continue,
+            };
+            // read_dir order is arbitrary, keep the outcome reproducible
+            files.sort();
+
+            for path in files {
+                if path.extension().is_none_or(|ext| ext != "desktop") {
+                    continue;
+                }
+                let content = match fs::read_to_string(&path) {
+                    Ok(content) => content,
+                    Err(_) => continue,
+                };
+                let entry = DesktopEntry {
+                    name: desktop_value(&content, "Name"),
+                    icon: desktop_value(&content, "Icon"),
+                };
+
+                let id = path.file_stem().map(|s| s.to_string_lossy().to_lowercase());
+                let wm_class = desktop_value(&content, "StartupWMClass").map(|c| c.to_lowercase());
+                for key in wm_class.into_iter().chain(id) {
+                    entries.entry(key).or_insert_with(|| entry.clone());
+                }
+            }
+        }
+        DesktopEntries { entries: entries }
+    }
+
+    /// The entry of a window class or app_id
+    pub fn lookup(&self, class: &str) -> Option<&DesktopEntry> {
+        self.entries.get(&class.to_lowercase())
+    }
+}
+
+/// Loads the config file, a missing file yields the defaults
+pub fn load_config(path: &Path) -> Result<Config, Box<dyn Error>> {
+    use std::io::prelude::*;
+
+    let mut content = String::new();
+    match File::open(path) {
+        Ok(mut file) => {
+            file.read_to_string(&mut content)
+                .map_err(|e| format!("could not read config file {}: {}", path.display(), e))?;
+        }
+        // a missing config file simply means defaults
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("could not open config file {}: {}", path.display(), e).into()),
+    }
+
+    toml::from_str(&content)
+        .map_err(|e| format!("could not parse config file {}: {}", path.display(), e).into())
+}
+
+/// The menu command: --dmenu wins over the config file, which wins over
+/// default
+pub fn dmenu_command(flag: Option<&str>, config: &Config, default: &str) -> String {
+    flag.map(|s| s.to_owned())
+        .or_else(|| config.dmenu_command.clone())
+        .unwrap_or_else(|| default.to_owned())
+}
+
+impl std::str::FromStr for Align {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Align, String> {
+        match s {
+            "left" => Ok(Align::Left),
+            "right" => Ok(Align::Right),
+            _ => Err(format!("unknown alignment '{}'", s)),
+        }
+    }
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SortOrder, String> {
+        match s {
+            "mru" => Ok(SortOrder::Mru),
+            "alpha" => Ok(SortOrder::Alpha),
+            "class" => Ok(SortOrder::Class),
+            _ => Err(format!("unknown sort order '{}'", s)),
+        }
+    }
+}
+
+/// Sorts windows, in place; `Mru` keeps the order of `get_windows_names`
+pub fn sort_windows(windows: &mut [Window], order: SortOrder) {
+    match order {
+        // get_windows_names already yields windows in focus order
+        SortOrder::Mru => (),
+        SortOrder::Alpha => windows.sort_by(|a, b| {
+            a.name.cmp(&b.name).then_with(|| a.class_name.cmp(&b.class_name))
+        }),
+        SortOrder::Class => windows.sort_by(|a, b| {
+            a.class_name.cmp(&b.class_name).then_with(|| a.name.cmp(&b.name))
+        }),
+    }
+}
+
+impl std::str::FromStr for MenuPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MenuPreset, String> {
+        match s {
+            "dmenu" => Ok(MenuPreset::Dmenu),
+            "bemenu" => Ok(MenuPreset::Bemenu),
+            "wofi" => Ok(MenuPreset::Wofi),
+            "fuzzel" => Ok(MenuPreset::Fuzzel),
+            _ => Err(format!("unknown menu preset '{}'", s)),
+        }
+    }
+}
+
+impl MenuPreset {
+    /// The command line of the preset
+    pub fn command(self) -> &'static str {
+        match self {
+            MenuPreset::Dmenu => DEFAULT_DMENU_COMMAND,
+            MenuPreset::Bemenu => "bemenu -i -l 20",
+            MenuPreset::Wofi => "wofi --dmenu -i",
+            MenuPreset::Fuzzel => "fuzzel --dmenu",
+        }
+    }
+
+    /// The option setting the number of lines, `-l` is wofi's location
+    pub fn lines_flag(self) -> &'static str {
+        match self {
+            MenuPreset::Wofi => "-L",
+            _ => LINES_FLAG,
+        }
+    }
+}
+
+impl std::str::FromStr for WorkspaceOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<WorkspaceOrder, String> {
+        match s {
+            "num" => Ok(WorkspaceOrder::Num),
+            "name" => Ok(WorkspaceOrder::Name),
+            "output" => Ok(WorkspaceOrder::Output),
+            _ => Err(format!("unknown workspace order '{}'", s)),
+        }
+    }
+}
+
+impl std::str::FromStr for KillSignal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<KillSignal, String> {
+        match s {
+            "term" => Ok(KillSignal::Term),
+            "kill" => Ok(KillSignal::Kill),
+            _ => Err(format!("unknown signal '{}'", s)),
+        }
+    }
+}
+
+/// Sorts workspaces, in place. By number puts numbered workspaces first,
+/// then named ones (num == -1) by name; by output groups them per output,
+/// each group by number.
+pub fn sort_workspaces(workspaces: &mut [Workspace], order: WorkspaceOrder) {
+    let by_num = |w: &Workspace| (w.num < 0, w.num, w.name.to_owned());
+    match order {
+        WorkspaceOrder::Num => workspaces.sort_by_key(by_num),
+        WorkspaceOrder::Name => workspaces.sort_by(|a, b| a.name.cmp(&b.name)),
+        WorkspaceOrder::Output => workspaces.sort_by_key(|w| (w.output.to_owned(), by_num(w))),
+    }
+}
+
+fn max_column_size<F>(windows: &[Window], column: F) -> usize
+    where F: Fn(&Window) -> Option<&String>
+{
+    windows.iter()
+        .map(|w| column(w).map_or(0, |s| s.width()))
+        .max().unwrap_or(0)
+}
+
+/// Display width of the widest class name, 0 for no windows
+pub fn max_class_name_size(windows: &[Window]) -> usize {
+    max_column_size(windows, |w| w.class_name.as_ref())
+}
+
+/// Display width of the longest workspace name
+pub fn max_workspace_name_size(windows: &[Window]) -> usize {
+    max_column_size(windows, |w| w.workspace.as_ref())
+}
+
+/// Display width of the longest WM_CLASS instance
+pub fn max_instance_name_size(windows: &[Window]) -> usize {
+    max_column_size(windows, |w| w.instance.as_ref())
+}
+
+/// Display width of the longest process id
+pub fn max_pid_size(windows: &[Window]) -> usize {
+    windows.iter().filter_map(|w| w.pid).map(|p| p.to_string().len()).max().unwrap_or(0)
+}
+
+/// Display width of the longest title, once cut to max_width if given
+pub fn max_title_size(windows: &[Window], max_width: Option<usize>) -> usize {
+    let size = max_column_size(windows, |w| Some(&w.name));
+    max_width.map_or(size, |max| cmp::min(size, max))
+}
+
+/// Display width of the widest comma separated list of marks
+pub fn max_marks_size(windows: &[Window]) -> usize {
+    windows.iter().map(|w| w.marks.join(",").width()).max().unwrap_or(0)
+}
+
+/// Menu entries and what they select. Entries are kept in a `Vec` rather
+/// than keyed by their label, so that two windows rendering to the same line
+/// (same class and title) don't overwrite each other. Colliding labels get a
+/// numeric suffix to stay selectable. Entries without a selectable are
+/// headers, shown but inert.
+pub type Mapping = Vec<(String, Option<Box<dyn Selectable>>)>;
+
+/// Adds an entry, suffixing its label if it is already taken
+pub fn push_unique(mapping: &mut Mapping, label: String, item: Box<dyn Selectable>) {
+    let mut key = label.clone();
+    let mut n = 1;
+    while mapping.iter().any(|(k, _)| *k == key) {
+        n += 1;
+        key = format!("{} ({})", label, n);
+    }
+    mapping.push((key, Some(item)));
+}
+
+/// Adds a line that cannot be selected
+pub fn push_header(mapping: &mut Mapping, title: &str) {
+    mapping.push((format!("── {} ──", title), None));
+}
+
+fn entry_selectable(entry: &(String, Option<Box<dyn Selectable>>)) -> Option<&dyn Selectable> {
+    entry.1.as_ref().map(|v| v.as_ref())
+}
+
+/// The entry whose label is exactly choice, or failing that the same once
+/// surrounding whitespace is ignored
+fn find_selection<'a>(mapping: &'a Mapping, choice: &str) -> Option<&'a dyn Selectable> {
+    mapping.iter()
+        .find(|&(k, _)| k == choice)
+        .or_else(|| mapping.iter().find(|&(k, _)| k.trim() == choice.trim()))
+        .and_then(entry_selectable)
+}
+
+/// Window changes the daemon keeps track of
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MruEvent {
+    Focus(i64),
+    Close(i64),
+}
+
+/// Applies a window event to the list of container ids, most recent first
+pub fn update_mru(mru: &mut Vec<i64>, event: MruEvent, size: usize) {
+    match event {
+        MruEvent::Focus(id) => {
+            mru.retain(|&w| w != id);
+            mru.insert(0, id);
+            mru.truncate(size);
+        }
+        MruEvent::Close(id) => mru.retain(|&w| w != id),
+    }
+}
+
+/// Reorders windows by the daemon's focus history; windows it hasn't seen
+/// focused keep their tree order, after the others
+pub fn sort_by_mru(windows: &mut [Window], mru: &[i64]) {
+    windows.sort_by_key(|w| {
+        mru.iter().position(|&id| id == w.con_id).unwrap_or(usize::MAX)
+    });
+}
+
+/// The entry whose label, or what it selects, equals choice regardless of
+/// case, for typed input
+pub fn find_selection_ignore_case<'a>(mapping: &'a Mapping, choice: &str)
+                                      -> Option<&'a dyn Selectable> {
+    let choice = choice.to_lowercase();
+    mapping.iter()
+        .filter_map(|entry| entry_selectable(entry).map(|s| (&entry.0, s)))
+        .find(|&(k, s)| k.to_lowercase() == choice || s.to_select_string().to_lowercase() == choice)
+        .map(|(_, s)| s)
+}
+
+/// Puts the entries found in history first, most recent first; the others
+/// keep their order. History holds what entries select, not their labels,
+/// which change with titles.
+pub fn boost_history(mapping: &mut Mapping, history: &[String]) {
+    mapping.sort_by_cached_key(|entry| {
+        entry_selectable(entry)
+            .and_then(|s| {
+                let key = s.to_select_string();
+                history.iter().position(|h| *h == key)
+            })
+            .unwrap_or(usize::MAX)
+    });
+}
+
+/// Prefixes selectable entries with their 1-based position, `" 3: "`
+/// right-aligned to the widest number; headers are left as they are
+pub fn number_entries(mapping: &mut Mapping) {
+    let count = mapping.iter().filter(|e| e.1.is_some()).count();
+    let width = count.to_string().len();
+    let mut n = 0;
+    for entry in mapping.iter_mut().filter(|e| e.1.is_some()) {
+        n += 1;
+        entry.0 = format!("{:>width$}: {}", n, entry.0, width = width);
+    }
+}
+
+/// How well text matches query, whose characters must all appear in order,
+/// ignoring case; runs of matches and matches at word starts score higher
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let mut query = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut previous: Option<char> = None;
+    let mut consecutive = false;
+    let mut started = false;
+
+    for c in text.chars() {
+        let q = match query.peek() {
+            Some(&q) => q,
+            None => break,
+        };
+        if c.to_lowercase().eq(std::iter::once(q)) {
+            score += 1;
+            if consecutive {
+                score += 5;
+            }
+            if previous.is_none_or(|p| !p.is_alphanumeric()) {
+                score += 3;
+            }
+            consecutive = true;
+            started = true;
+            query.next();
+        } else {
+            // characters skipped between two matches cost a little
+            if started {
+                score -= 1;
+            }
+            consecutive = false;
+        }
+        previous = Some(c);
+    }
+
+    if query.peek().is_some() {
+        None
+    } else {
+        Some(score)
+    }
+}
+
+/// The selectable entries matching query, best first; ties keep their order
+pub fn fuzzy_filter(mapping: Mapping, query: &str) -> Mapping {
+    let mut scored = mapping.into_iter()
+        .filter(|e| e.1.is_some())
+        .filter_map(|e| fuzzy_score(query, &e.0).map(|score| (score, e)))
+        .collect::<Vec<_>>();
+    scored.sort_by_key(|&(score, _)| -score);
+    scored.into_iter().map(|(_, e)| e).collect()
+}
+
+/// Windows grouped per workspace, groups ordered by their first window
+pub fn group_by_workspace(windows: Vec<Window>) -> Vec<(Option<String>, Vec<Window>)> {
+    let mut groups: Vec<(Option<String>, Vec<Window>)> = Vec::new();
+    for w in windows {
+        match groups.iter().position(|(ws, _)| *ws == w.workspace) {
+            Some(i) => groups[i].1.push(w),
+            None => groups.push((w.workspace.clone(), vec![w])),
+        }
+    }
+    groups
+}
+
+/// Interprets the selected line, by label or by rofi index. The text
+/// returned is trimmed, empty when the menu was cancelled.
+fn resolve_selection<'a>(mapping: &'a Mapping, line: &str, indexed: bool)
+                         -> (Option<&'a dyn Selectable>, String) {
+    if !indexed {
+        return (find_selection(mapping, line), line.trim().to_owned());
+    }
+
+    let mut parts = line.trim().splitn(2, ' ');
+    let index = parts.next().and_then(|i| i.parse::<usize>().ok());
+    let text = parts.next().unwrap_or("").to_owned();
+
+    (index.and_then(|i| mapping.get(i)).and_then(entry_selectable), text)
+}
+
+/// The menu command to run: the one of the command line, else of the
+/// environment, else of the config file, else default
+pub fn resolve_dmenu_command(flag: Option<&str>, env: Option<&str>, config: Option<&str>,
+                             default: &str) -> String {
+    flag.or(env).or(config).unwrap_or(default).to_owned()
+}
+
+/// The first non-empty variable of `DMENU_ENV_VARS`
+pub fn dmenu_from_env() -> Option<String> {
+    DMENU_ENV_VARS.iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|command| !command.trim().is_empty())
+}
+
+/// Appends `-p prompt` to a menu command, quoted for `split_exec_args`
+pub fn with_prompt(command: &str, prompt: &str) -> String {
+    format!("{} -p {}", command, quote_arg(prompt))
+}
+
+/// Height of a menu showing count entries, no taller than max
+pub fn menu_lines(count: usize, max: usize) -> usize {
+    std::cmp::min(count, max)
+}
+
+/// Sets the lines option (flag, `-l` for dmenu or rofi) of a menu command,
+/// replacing the one already there if any
+pub fn with_lines(command: &str, flag: &str, lines: usize) -> String {
+    let re = Regex::new(&format!(r"(^|\s){}\s+\d+", regex::escape(flag))).unwrap();
+    if re.is_match(command) {
+        re.replace(command, format!("${{1}}{} {}", flag, lines).as_str()).into_owned()
+    } else {
+        format!("{} {} {}", command, flag, lines)
+    }
+}
+
+/// What came back from the menu
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuOutput {
+    /// The raw selection
+    pub text: String,
+    /// Exit code of the menu program, `None` if it has none
+    pub exit_code: Option<i32>,
+}
+
+impl MenuOutput {
+    /// Whether the menu was dismissed: dmenu and rofi exit with 1 on
+    /// Escape, rofi's custom keys (10 to 28) are selections
+    pub fn cancelled(&self) -> bool {
+        match self.exit_code {
+            Some(0) | None => false,
+            Some(code) => !(10..=28).contains(&code),
+        }
+    }
+}
+
+/// Action bound to rofi's `-kb-custom-N` keys, which make it exit with
+/// 9 + N instead of 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyAction {
+    Focus,
+    Bring,
+    Close,
+}
+
+/// The action of `-kb-custom-1` to `-kb-custom-3`
+pub fn custom_key_action(exit_code: i32) -> Option<KeyAction> {
+    match exit_code {
+        10 => Some(KeyAction::Focus),
+        11 => Some(KeyAction::Bring),
+        12 => Some(KeyAction::Close),
+        _ => None,
+    }
+}
+
+/// The i3 command carrying out action on window
+pub fn key_action_command(action: KeyAction, window: &dyn Selectable) -> String {
+    match action {
+        KeyAction::Focus => format!("{} focus", window.to_select_string()),
+        KeyAction::Bring => bring_command(window),
+        KeyAction::Close => format!("{} kill", window.to_select_string()),
+    }
+}
+
+/// What an entry of the combined menu does: windows follow the custom keys
+/// and are focused otherwise, workspaces are switched to, or the focused
+/// window is moved there with the move key
+pub fn menu_entry_command(entry: &dyn Selectable, action: Option<KeyAction>) -> Option<String> {
+    if entry.as_window().is_some() {
+        return Some(key_action_command(action.unwrap_or(KeyAction::Focus), entry));
+    }
+    let workspace = entry.as_workspace()?;
+    match action {
+        Some(KeyAction::Bring) => {
+            Some(format!("move container to workspace {}", quote_arg(&workspace.name)))
+        }
+        Some(KeyAction::Close) => None,
+        Some(KeyAction::Focus) | None => Some(workspace_command(&workspace.name)),
+    }
+}
+
+/// Something letting the user pick one of the menu lines
+pub trait Menu {
+    /// Shows options, one per line, and returns the raw selection
+    fn choose(&self, options: &str, prompt: Option<&str>) -> Result<MenuOutput, Box<dyn Error>>;
+
+    /// Whether the selection comes back as rofi's `-format 'i s'`
+    fn indexed(&self) -> bool {
+        false
+    }
+}
+
+/// A dmenu-like program reading its options on stdin
+#[derive(Debug)]
+pub struct DmenuBackend {
+    pub command: String,
+    pub indexed: bool,
+    /// Trace the command line on stderr before running it
+    pub verbose: bool,
+    /// Size the menu to its entries, up to this many lines
+    pub max_lines: Option<usize>,
+    /// Option taking the number of lines, see `MenuPreset::lines_flag`
+    pub lines_flag: &'static str,
+}
+
+impl Menu for DmenuBackend {
+    fn choose(&self, options: &str, prompt: Option<&str>) -> Result<MenuOutput, Box<dyn Error>> {
+        // lines first, the prompt text must not be mistaken for an option
+        let command = match self.max_lines {
+            Some(max) => {
+                with_lines(&self.command, self.lines_flag, menu_lines(options.lines().count(), max))
+            }
+            None => self.command.to_owned(),
+        };
+        let command = match prompt {
+            Some(prompt) => with_prompt(&command, prompt),
+            None => command,
+        };
+        if self.verbose {
+            eprintln!("menu command: {}", command);
+        }
+        let (text, status) = exec_dmenu(&command, options, self.verbose)?;
+        Ok(MenuOutput { text: text, exit_code: status.code() })
+    }
+
+    fn indexed(&self) -> bool {
+        self.indexed
+    }
+}
+
+/// Writes the options to writer and reads the selected line back from
+/// reader, to be used as a component in a pipeline
+#[derive(Debug)]
+pub struct StreamBackend<R, W> {
+    reader: RefCell<R>,
+    writer: RefCell<W>,
+}
+
+impl<R: io::BufRead, W: io::Write> StreamBackend<R, W> {
+    pub fn new(reader: R, writer: W) -> StreamBackend<R, W> {
+        StreamBackend { reader: RefCell::new(reader), writer: RefCell::new(writer) }
+    }
+}
+
+impl<R: io::BufRead, W: io::Write> Menu for StreamBackend<R, W> {
+    fn choose(&self, options: &str, _prompt: Option<&str>) -> Result<MenuOutput, Box<dyn Error>> {
+        let mut writer = self.writer.borrow_mut();
+        writeln!(writer, "{}", options)?;
+        writer.flush()?;
+
+        let mut line = String::new();
+        self.reader.borrow_mut().read_line(&mut line)?;
+        Ok(MenuOutput { text: line, exit_code: None })
+    }
+}
+
+/// Numbered list of the options on writer, the answer read from reader: for
+/// terminals, when there's no graphical menu to run
+#[derive(Debug)]
+pub struct TerminalBackend<R, W> {
+    reader: RefCell<R>,
+    writer: RefCell<W>,
+}
+
+impl<R: io::BufRead, W: io::Write> TerminalBackend<R, W> {
+    pub fn new(reader: R, writer: W) -> TerminalBackend<R, W> {
+        TerminalBackend { reader: RefCell::new(reader), writer: RefCell::new(writer) }
+    }
+}
+
+impl<R: io::BufRead, W: io::Write> Menu for TerminalBackend<R, W> {
+    fn choose(&self, options: &str, prompt: Option<&str>) -> Result<MenuOutput, Box<dyn Error>> {
+        let mut writer = self.writer.borrow_mut();
+        let lines = options.lines().collect::<Vec<_>>();
+        let width = lines.len().to_string().len();
+        for (i, line) in lines.iter().enumerate() {
+            writeln!(writer, "{:>width$}) {}", i + 1, menu_label(line), width = width)?;
+        }
+        write!(writer, "{}> ", prompt.unwrap_or(""))?;
+        writer.flush()?;
+
+        let mut answer = String::new();
+        if self.reader.borrow_mut().read_line(&mut answer)? == 0 {
+            // end of input, same as escaping out of dmenu
+            return Ok(MenuOutput { text: String::new(), exit_code: Some(1) });
+        }
+        Ok(MenuOutput { text: terminal_choice(options, &answer), exit_code: None })
+    }
+}
+
+/// The option numbered answer (from 1), or the answer itself as typed text
+pub fn terminal_choice(options: &str, answer: &str) -> String {
+    let answer = answer.trim();
+    answer.parse::<usize>().ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|i| options.lines().nth(i))
+        .map(|line| menu_label(line).to_owned())
+        .unwrap_or_else(|| answer.to_owned())
+}
+
+/// A menu line without rofi's control sequences
+pub fn menu_label(line: &str) -> &str {
+    line.split('\0').next().unwrap_or(line)
+}
+
+/// rofi's script mode (`-modi name:command`): rofi runs the command a first
+/// time for the entries, then again with the picked line as argument
+#[derive(Debug)]
+pub struct RofiScriptBackend<W> {
+    /// `ROFI_RETV`: 0 on the first run, 1 for an entry, 2 for typed text
+    /// and 10 to 28 for the custom keys
+    pub retv: i32,
+    /// The line rofi passed back
+    pub selection: Option<String>,
+    /// Let text that isn't an entry be typed in
+    pub custom: bool,
+    writer: RefCell<W>,
+}
+
+impl<W: io::Write> RofiScriptBackend<W> {
+    /// The entries are written to writer, rofi's end of the pipe
+    pub fn new(retv: i32, selection: Option<String>, custom: bool, writer: W)
+               -> RofiScriptBackend<W> {
+        RofiScriptBackend {
+            retv: retv,
+            selection: selection,
+            custom: custom,
+            writer: RefCell::new(writer),
+        }
+    }
+}
+
+impl<W: io::Write> Menu for RofiScriptBackend<W> {
+    fn choose(&self, options: &str, prompt: Option<&str>) -> Result<MenuOutput, Box<dyn Error>> {
+        match self.selection {
+            Some(ref selection) if self.retv != 0 => {
+                let exit_code = if self.retv >= 10 { Some(self.retv) } else { None };
+                Ok(MenuOutput { text: selection.to_owned(), exit_code: exit_code })
+            }
+            _ => {
+                let mut writer = self.writer.borrow_mut();
+                write!(writer, "{}", rofi_script_header(prompt, self.custom))?;
+                writeln!(writer, "{}", options)?;
+                writer.flush()?;
+                // rofi shows the entries once we're done, nothing to act on yet
+                Ok(MenuOutput { text: String::new(), exit_code: Some(1) })
+            }
+        }
+    }
+}
+
+/// The control lines setting up rofi in script mode
+pub fn rofi_script_header(prompt: Option<&str>, custom: bool) -> String {
+    let mut header = String::new();
+    if let Some(prompt) = prompt {
+        header.push_str(&format!("\0prompt\x1f{}\n", prompt));
+    }
+    if !custom {
+        header.push_str("\0no-custom\x1ftrue\n");
+    }
+    header.push_str("\0use-hot-keys\x1ftrue\n");
+    header
+}
+
+/// Always answers with the same selection, for tests and scripting
+#[derive(Debug)]
+pub struct MockBackend {
+    pub choice: String,
+}
+
+impl Menu for MockBackend {
+    fn choose(&self, _options: &str, _prompt: Option<&str>) -> Result<MenuOutput, Box<dyn Error>> {
+        Ok(MenuOutput { text: self.choice.to_owned(), exit_code: None })
+    }
+}
+
+/// The lines given to the menu, one per entry
+pub fn menu_options(mapping: &Mapping) -> String {
+    mapping.iter()
+        .map(|entry| {
+            let icon = entry_selectable(entry)
+                .and_then(|s| s.as_window())
+                .and_then(|w| w.icon.as_ref());
+            menu_row(&entry.0, icon.map(|s| s.as_str()))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A menu line, with rofi's icon control sequence when there is an icon
+pub fn menu_row(label: &str, icon: Option<&str>) -> String {
+    match icon {
+        Some(icon) => format!("{}\0icon\x1f{}", label, icon),
+        None => label.to_owned(),
+    }
+}
+
+/// The entry picked in a menu, none for typed text or a header
+pub type Picked<'a> = Option<&'a dyn Selectable>;
+
+/// Shows the mapping in the menu and returns what was picked, along with
+/// the raw text for menus that allow free input
+pub fn choose<'a>(menu: &dyn Menu, mapping: &'a Mapping, prompt: Option<&str>)
+              -> Result<(Picked<'a>, String), Box<dyn Error>> {
+    let (selection, text, _) = choose_with_code(menu, mapping, prompt)?;
+    Ok((selection, text))
+}
+
+/// Same as `choose`, the menu's exit code included
+pub fn choose_with_code<'a>(menu: &dyn Menu, mapping: &'a Mapping, prompt: Option<&str>)
+                        -> Result<(Picked<'a>, String, Option<i32>), Box<dyn Error>> {
+    let output = menu.choose(&menu_options(mapping), prompt)?;
+    if output.cancelled() {
+        return Ok((None, String::new(), output.exit_code));
+    }
+    // only the line break goes: labels can start with the padding of markers
+    let line = output.text.trim_end_matches(['\n', '\r']);
+    let (selection, text) = resolve_selection(mapping, line, menu.indexed());
+    Ok((selection, text, output.exit_code))
+}
+
+/// The window as a tab-separated `id class name` line, for scripts; the id
+/// is left empty for native Wayland clients
+pub fn selection_line(window: &Window) -> String {
+    let id = window.id.map(|id| id.to_string()).unwrap_or_default();
+    format!("{}\t{}\t{}", id, window.class_name.as_ref().map_or("", |c| c.as_str()), window.name)
+}
+
+/// Sends signal to the window's process, for clients too stuck to answer
+/// i3's `kill`
+pub fn kill_pid(window: &Window, signal: KillSignal, options: SendOptions,
+                out: &mut dyn io::Write) -> Result<(), Box<dyn Error>> {
+    let pid = window.pid.ok_or_else(|| format!("no process id known for '{}'", window.name))?;
+    let (number, name) = match signal {
+        KillSignal::Term => (libc::SIGTERM, "TERM"),
+        KillSignal::Kill => (libc::SIGKILL, "KILL"),
+    };
+    if options.dry_run || options.verbose {
+        writeln!(out, "kill -{} {}", name, pid)?;
+    }
+    if options.dry_run {
+        return Ok(());
+    }
+    if unsafe { libc::kill(pid as libc::pid_t, number) } != 0 {
+        let e = io::Error::last_os_error();
+        return Err(format!("could not kill process {}: {}", pid, e).into());
+    }
+    Ok(())
+}
+
+/// Move the window to a named workspace, which i3 creates if it is new
+pub fn move_to_workspace_command(window: &dyn Selectable, workspace: &str) -> String {
+    format!("{} move workspace {}", window.to_select_string(), quote_arg(workspace))
+}
+
+/// Move the window to the visible workspace of another output
+pub fn move_to_output_command(window: &dyn Selectable, output: &str) -> String {
+    format!("{} move output {}", window.to_select_string(), quote_arg(output))
+}
+
+/// Moves the window to the next (or previous) workspace; following it means
+/// focusing it again once it's there
+pub fn move_relative_command(window: &dyn Selectable, next: bool, follow: bool) -> String {
+    let select = window.to_select_string();
+    let direction = if next { "next" } else { "prev" };
+    let mut command = format!("{} move container to workspace {}", select, direction);
+    if follow {
+        command.push_str(&format!("; {} focus", select));
+    }
+    command
+}
+
+/// Brings the window to the current workspace and focuses it, in one
+/// command line so i3 runs both or neither
+pub fn bring_command(window: &dyn Selectable) -> String {
+    let select = window.to_select_string();
+    format!("{} move workspace current; {} focus", select, select)
+}
+
+pub fn move_to_scratchpad_command(window: &dyn Selectable) -> String {
+    format!("{} move scratchpad", window.to_select_string())
+}
+
+/// Toggles fullscreen, focusing the window first when follow is set so the
+/// result is visible; with a comma both commands share the criteria
+pub fn fullscreen_command(window: &dyn Selectable, follow: bool) -> String {
+    if follow {
+        format!("{} focus, fullscreen toggle", window.to_select_string())
+    } else {
+        format!("{} fullscreen toggle", window.to_select_string())
+    }
+}
+
+/// Swaps two windows; the second goes by container id, which Wayland
+/// windows have too
+pub fn swap_command(first: &Window, second: &Window) -> String {
+    format!("{} swap container with con_id {}", first.to_select_string(), second.con_id)
+}
+
+/// Quotes an argument for i3's command parser
+fn quote_arg(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Rename the selected workspace, both names quoted
+pub fn rename_workspace_command(workspace: &dyn Selectable, new_name: &str) -> String {
+    format!("rename workspace {} to {}",
+            quote_arg(&workspace.to_select_string()), quote_arg(new_name))
+}
+
+/// `mark` replaces the window's other marks, like i3's default
+pub fn mark_command(window: &dyn Selectable, mark: &str) -> String {
+    format!("{} mark {}", window.to_select_string(), quote_arg(mark))
+}
+
+/// Focuses the window. i3 keeps an urgency hint up a while after switching
+/// to its workspace (`force_display_urgency_hint`), focusing it a second time
+/// once it's shown clears it on the spot.
+pub fn focus_command(window: &dyn Selectable, clear_urgency: bool) -> String {
+    let focus = format!("{} focus", window.to_select_string());
+    if clear_urgency {
+        format!("{}; {}", focus, focus)
+    } else {
+        focus
+    }
+}
+
+/// Switches to the workspace called name, quoted so spaces and quotes
+/// make it through i3's parser
+pub fn workspace_command(name: &str) -> String {
+    format!("workspace {}", quote_arg(name))
+}
+
+/// Switch to a typed workspace; numeric names go through `number` so i3
+/// sorts them with the others
+pub fn new_workspace_command(name: &str) -> String {
+    if name.parse::<i32>().is_ok() {
+        format!("workspace number {}", name)
+    } else {
+        workspace_command(name)
+    }
+}
+
+/// Splits a command line into program and arguments, shell style: quotes
+/// group words, a backslash escapes the next character outside of quotes,
+/// and `\"` or `\\` inside double quotes. `None` if there is no program.
+pub fn split_exec_args(command: &str) -> Option<(String, Vec<String>)> {
+    let mut chars = command.chars();
+    let mut args = Vec::new();
+
+    let mut buf = String::new();
+    // tracked apart from buf so that `""` still makes an (empty) argument
+    let mut in_arg = false;
+    let mut matching_char: Option<char> = None;
+
+    while let Some(ch) = chars.next() {
+        match matching_char {
+            Some('\'') => match ch {
+                '\'' => matching_char = None,
+                _ => buf.push(ch),
+            },
+            Some(_) => match ch {
+                '"' => matching_char = None,
+                '\\' => match chars.next() {
+                    Some(next @ '"') | Some(next @ '\\') => buf.push(next),
+                    Some(next) => {
+                        buf.push('\\');
+                        buf.push(next);
+                    }
+                    None => buf.push('\\'),
+                },
+                _ => buf.push(ch),
+            },
+            None => match ch {
+                ' ' | '\t' => if in_arg {
+                    args.push(buf.to_owned());
+                    buf = String::new();
+                    in_arg = false;
+                },
+                '"' | '\'' => {
+                    matching_char = Some(ch);
+                    in_arg = true;
+                }
+                '\\' => {
+                    if let Some(next) = chars.next() {
+                        buf.push(next);
+                    }
+                    in_arg = true;
+                }
+                _ => {
+                    buf.push(ch);
+                    in_arg = true;
+                }
+            },
+        }
+    }
+
+    if in_arg {
+        args.push(buf);
+    }
+
+    if args.is_empty() {
+        return None;
+    }
+    let program = args.remove(0);
+
+    Some((program, args))
+}
+
+/// What is asked of i3, so that the listing and the commands can be driven
+/// without one running
+pub trait Ipc {
+    /// The whole layout tree
+    fn tree(&mut self) -> Result<reply::Node, Box<dyn Error>>;
+
+    /// All workspaces, in i3's order
+    fn workspaces(&mut self) -> Result<Vec<Workspace>, Box<dyn Error>>;
+
+    /// All marks, in i3's order
+    fn marks(&mut self) -> Result<Vec<Mark>, Box<dyn Error>>;
+
+    /// Active outputs
+    fn outputs(&mut self) -> Result<Vec<Output>, Box<dyn Error>>;
+
+    /// Runs command, i3's reply tells whether it could
+    fn command(&mut self, command: &str) -> Result<reply::Command, Box<dyn Error>>;
+}
+
+impl Ipc for I3Connection {
+    fn tree(&mut self) -> Result<reply::Node, Box<dyn Error>> {
+        self.get_tree()
+            .map_err(|e| format!("could not get the window tree from i3: {}", e).into())
+    }
+
+    fn workspaces(&mut self) -> Result<Vec<Workspace>, Box<dyn Error>> {
+        Ok(self.get_workspaces()
+            .map_err(|e| format!("could not get workspaces from i3: {}", e))?
+            .into_iter()
+            .map(|w| Workspace {
+                name: w.name,
+                num: w.num,
+                output: w.output,
+                focused: w.focused,
+                urgent: w.urgent,
+                window_count: None,
+            })
+            .collect::<Vec<_>>())
+    }
+
+    fn marks(&mut self) -> Result<Vec<Mark>, Box<dyn Error>> {
+        Ok(self.get_marks()
+            .map_err(|e| format!("could not get marks from i3: {}", e))?
+            .into_iter()
+            .map(|m| Mark { name: m })
+            .collect::<Vec<_>>())
+    }
+
+    fn outputs(&mut self) -> Result<Vec<Output>, Box<dyn Error>> {
+        Ok(self.get_outputs()
+            .map_err(|e| format!("could not get outputs from i3: {}", e))?
+            .into_iter()
+            .filter(|o| o.active)
+            .map(|o| Output { name: o.name })
+            .collect::<Vec<_>>())
+    }
+
+    fn command(&mut self, command: &str) -> Result<reply::Command, Box<dyn Error>> {
+        // the inherent method, not this one
+        I3Connection::command(self, command)
+            .map_err(|e| format!("could not send command '{}' to i3: {}", command, e).into())
+    }
+}
+
+/// An i3 with a fixed state, recording the commands it is sent instead of
+/// running them, for tests and scripting
+#[derive(Debug, Default)]
+pub struct MockConnection {
+    /// Laid out in a tree by workspace, in this order
+    pub windows: Vec<Window>,
+    pub workspaces: Vec<Workspace>,
+    pub marks: Vec<Mark>,
+    pub outputs: Vec<Output>,
+    /// Every command sent, in order
+    pub commands: Vec<String>,
+    /// The error the tree is answered with, as when i3 went away
+    pub tree_error: Option<String>,
+    /// The error i3 answers every command with, they all succeed otherwise
+    pub error: Option<String>,
+}
+
+impl Ipc for MockConnection {
+    fn tree(&mut self) -> Result<reply::Node, Box<dyn Error>> {
+        match self.tree_error {
+            Some(ref e) => Err(format!("could not get the window tree from i3: {}", e).into()),
+            None => Ok(tree_node(&self.windows)),
+        }
+    }
+
+    fn workspaces(&mut self) -> Result<Vec<Workspace>, Box<dyn Error>> {
+        Ok(self.workspaces.clone())
+    }
+
+    fn marks(&mut self) -> Result<Vec<Mark>, Box<dyn Error>> {
+        Ok(self.marks.clone())
+    }
+
+    fn outputs(&mut self) -> Result<Vec<Output>, Box<dyn Error>> {
+        Ok(self.outputs.clone())
+    }
+
+    fn command(&mut self, command: &str) -> Result<reply::Command, Box<dyn Error>> {
+        self.commands.push(command.to_owned());
+        Ok(reply::Command {
+            outcomes: vec![reply::CommandOutcome {
+                success: self.error.is_none(),
+                error: self.error.clone(),
+            }],
+        })
+    }
+}
+
+// A node of nodetype with nothing in it or set on it
+fn empty_node(nodetype: reply::NodeType, id: i64, name: Option<String>) -> reply::Node {
+    reply::Node {
+        focus: Vec::new(),
+        nodes: Vec::new(),
+        floating_nodes: Vec::new(),
+        id: id,
+        name: name,
+        nodetype: nodetype,
+        rect: (0, 0, 0, 0),
+        window: None,
+        urgent: false,
+        focused: false,
+        class_name: None,
+        instance: None,
+        app_id: None,
+        marks: Vec::new(),
+        window_type: None,
+        pid: None,
+    }
+}
+
+// The leaf i3 (or Sway, for a window without X id) would report for window
+fn window_node(window: &Window) -> reply::Node {
+    let mut node = empty_node(reply::NodeType::Con, window.con_id, Some(window.name.to_owned()));
+    node.window = window.id;
+    if window.id.is_some() {
+        node.class_name = window.class_name.to_owned();
+    } else {
+        node.app_id = window.class_name.to_owned();
+    }
+    node.instance = window.instance.to_owned();
+    node.focused = window.focused;
+    node.urgent = window.urgent;
+    node.marks = window.marks.to_owned();
+    node.pid = window.pid;
+    node
+}
+
+// Windows laid out by workspace, keeping their order; the scratchpad
+// workspace goes to i3's hidden output, the others to a single one
+fn tree_node(windows: &[Window]) -> reply::Node {
+    let mut root = empty_node(reply::NodeType::Root, 0, Some("root".to_owned()));
+    for w in windows {
+        let output = match w.output {
+            Some(ref output) => output.as_str(),
+            None if w.workspace.as_ref().is_some_and(|n| n == "__i3_scratch") => {
+                SCRATCHPAD_OUTPUT
+            }
+            None => "eDP-1",
+        };
+        let output = child_node(&mut root.nodes, reply::NodeType::Output,
+                                Some(&output.to_owned()));
+        let workspace = child_node(&mut output.nodes, reply::NodeType::Workspace,
+                                   w.workspace.as_ref());
+        if w.floating {
+            let mut floating = empty_node(reply::NodeType::FloatingCon, 0, None);
+            floating.nodes.push(window_node(w));
+            workspace.floating_nodes.push(floating);
+        } else {
+            workspace.nodes.push(window_node(w));
+        }
+    }
+    root
+}
+
+// The node called name, added to nodes if there's none yet
+fn child_node<'a>(nodes: &'a mut Vec<reply::Node>, nodetype: reply::NodeType,
+                  name: Option<&String>) -> &'a mut reply::Node {
+    let name = name.cloned().unwrap_or_default();
+    match nodes.iter().position(|n| n.name.as_ref() == Some(&name)) {
+        Some(i) => &mut nodes[i],
+        None => {
+            nodes.push(empty_node(nodetype, 0, Some(name)));
+            nodes.last_mut().unwrap()
+        }
+    }
+}
+
+/// The whole layout tree, fetch it once and hand it to whatever needs it
+pub fn get_tree(conn: &mut dyn Ipc) -> Result<reply::Node, Box<dyn Error>> {
+    conn.tree()
+}
+
+/// All listable windows of the tree, most recently focused first. Windows
+/// hidden in the scratchpad are left out.
+pub fn get_windows_names(tree: &reply::Node, ignores: &Ignores) -> Vec<Window> {
+    collect_windows(tree, ignores, false)
+}
+
+/// Windows currently hidden in the scratchpad
+pub fn get_scratchpad_windows(tree: &reply::Node, ignores: &Ignores) -> Vec<Window> {
+    collect_windows(tree, ignores, true)
+}
+
+fn collect_windows(tree: &reply::Node, ignores: &Ignores, scratchpad: bool) -> Vec<Window> {
+    flatten_nodes(tree, Location::default()).into_iter()
+        .filter(|&(location, n)| location.scratchpad == scratchpad && filter_node(n, ignores))
+        .filter_map(|(location, n)| {
+            window_from_node(n, location, ignores.unnamed.as_deref())
+        })
+        .collect::<Vec<_>>()
+}
+
+/// The window of node, titled unnamed when it has no name of its own
+pub fn window_from_node(node: &reply::Node, location: Location, unnamed: Option<&str>)
+                        -> Option<Window> {
+    node.name.as_deref().or(unnamed).map(|name| Window {
+        id: node.window,
+        con_id: node.id,
+        name: name.to_owned(),
+        // native Wayland clients only set an app_id
+        class_name: node.class_name.to_owned().or_else(|| node.app_id.to_owned()),
+        instance: node.instance.to_owned(),
+        focused: node.focused,
+        urgent: node.urgent,
+        floating: location.floating,
+        workspace: location.workspace.map(|s| s.to_owned()),
+        output: location.output.map(|s| s.to_owned()),
+        marks: node.marks.to_owned(),
+        rect: node.rect,
+        pid: node.pid,
+        icon: None,
+    })
+}
+
+/// Whether node is a window that should be listed
+pub fn filter_node(node: &reply::Node, ignores: &Ignores) -> bool {
+    // if neither, it's a split container, not a window
+    (node.window.is_some() || node.app_id.is_some()) &&
+    match node.name {
+        Some(ref name) => !ignores.names.contains(name),
+        None => ignores.unnamed.is_some()
+    } &&
+    match node.class_name.as_ref().or(node.app_id.as_ref()) {
+        Some(name) => !ignores.classes.contains(name),
+        None => true
+    } &&
+    match node.window_type {
+        Some(ref window_type) => !ignores.types.contains(window_type),
+        None => true
+    }
+}
+
+/// All workspaces, numbered ones first
+pub fn get_workspaces(conn: &mut dyn Ipc) -> Result<Vec<Workspace>, Box<dyn Error>> {
+    let mut workspaces = conn.workspaces()?;
+    sort_workspaces(&mut workspaces, WorkspaceOrder::Num);
+
+    Ok(workspaces)
+}
+
+/// The workspace having focus, there is one unless i3 is misbehaving
+pub fn focused_workspace(workspaces: &[Workspace]) -> Option<&Workspace> {
+    workspaces.iter().find(|w| w.focused)
+}
+
+/// Fills in `window_count` from the listed windows
+pub fn count_windows(workspaces: &mut [Workspace], windows: &[Window]) {
+    for ws in workspaces {
+        let on_workspace = |w: &&Window| {
+            w.workspace.as_ref() == Some(&ws.name) && w.output.as_ref() == Some(&ws.output)
+        };
+        ws.window_count = Some(windows.iter().filter(on_workspace).count());
+    }
+}
+
+/// Workspaces by name; names used on several outputs are suffixed with the
+/// output to tell them apart, and counts are appended when known. Urgent
+/// workspaces are prefixed with marker, if given.
+pub fn workspace_mapping(workspaces: Vec<Workspace>, marker_urgent: Option<&str>) -> Mapping {
+    let mut mapping: Mapping = Vec::new();
+    let shared = |name: &str| workspaces.iter().filter(|w| w.name == name).count() > 1;
+    let labels = workspaces.iter()
+        .map(|w| {
+            let mut label = w.name.to_owned();
+            if shared(&w.name) {
+                label = format!("{} ({})", label, w.output);
+            }
+            if let Some(count) = w.window_count {
+                label = format!("{} ({})", label, count);
+            }
+            match marker_urgent {
+                Some(marker) => {
+                    let slot = if w.urgent { marker } else { "" };
+                    format!("{} {}", pad(slot, marker.width()), label)
+                }
+                None => label,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    for (label, w) in labels.into_iter().zip(workspaces) {
+        push_unique(&mut mapping, label, Box::new(w));
+    }
+    mapping
+}
+
+/// All marks, in the order i3 reports them
+pub fn get_marks(conn: &mut dyn Ipc) -> Result<Vec<Mark>, Box<dyn Error>> {
+    conn.marks()
+}
+
+/// Puts the `back_and_forth` entry first; a real workspace with the same
+/// name gets suffixed like any other collision
+pub fn prepend_last_workspace(mapping: Mapping) -> Mapping {
+    let last: Box<dyn Selectable> = Box::new(LastWorkspace);
+    let mut with_last: Mapping = vec![(LAST_WORKSPACE_LABEL.to_owned(), Some(last))];
+    for (label, item) in mapping {
+        match item {
+            Some(item) => push_unique(&mut with_last, label, item),
+            None => with_last.push((label, None)),
+        }
+    }
+    with_last
+}
+
+/// Active outputs
+pub fn get_outputs(conn: &mut dyn Ipc) -> Result<Vec<Output>, Box<dyn Error>> {
+    conn.outputs()
+}
+
+/// Leaves below node, most recently focused first, with their location in
+/// the tree: each container's children are visited in the order of its focus
+/// stack.
+pub fn flatten_nodes<'a>(node: &'a reply::Node, location: Location<'a>)
+                         -> Vec<(Location<'a>, &'a reply::Node)> {
+    // walked with an explicit stack, deeply nested layouts must not blow
+    // the call stack
+    let mut leaves = Vec::new();
+    let mut stack = vec![(location, node)];
+
+    while let Some((mut location, node)) = stack.pop() {
+        match node.nodetype {
+            reply::NodeType::Output => {
+                location.output = node.name.as_deref();
+                location.scratchpad = location.output == Some(SCRATCHPAD_OUTPUT);
+            }
+            reply::NodeType::Workspace => {
+                location.workspace = node.name.as_deref();
+            }
+            _ => (),
+        }
+
+        if node.nodes.is_empty() && node.floating_nodes.is_empty() {
+            leaves.push((location, node));
+        } else {
+            // reversed so that the most recently focused child is popped first
+            stack.extend(focus_ordered(node).into_iter().rev().map(|(floating, n)| {
+                (Location { floating: location.floating || floating, ..location }, n)
+            }));
+        }
+    }
+
+    leaves
+}
+
+// Children flagged when floating
+fn focus_ordered(node: &reply::Node) -> Vec<(bool, &reply::Node)> {
+    // floating windows live in their own floating_con under
+    // floating_nodes; only their leaves are actual windows
+    let mut children = node.nodes.iter().map(|n| (false, n))
+        .chain(node.floating_nodes.iter().map(|n| (true, n)))
+        .collect::<Vec<_>>();
+    children.sort_by_key(|&(_, c)| {
+        node.focus.iter().position(|&id| id == c.id).unwrap_or(usize::MAX)
+    });
+    children
+}
+
+/// Expands `$VAR`, `${VAR}` and a leading `~` in arg, from the process
+/// environment. Unset variables expand to nothing and are added to unset.
+pub fn expand_env(arg: &str, unset: &mut Vec<String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = arg;
+    if rest == "~" || rest.starts_with("~/") {
+        match env::var("HOME") {
+            Ok(home) => expanded.push_str(&home),
+            Err(_) => unset.push("HOME".to_owned()),
+        }
+        rest = &rest[1..];
+    }
+
+    let mut chars = rest.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '$' {
+            expanded.push(ch);
+            continue;
+        }
+
+        let mut name = String::new();
+        if chars.peek() == Some(&'{') {
+            chars.next();
+            for c in chars.by_ref() {
+                if c == '}' {
+                    break;
+                }
+                name.push(c);
+            }
+        } else {
+            // like sh, names start with a letter: `$1` is left to the command
+            while let Some(&c) = chars.peek() {
+                let digit = !name.is_empty() && c.is_ascii_digit();
+                if !(c.is_ascii_alphabetic() || c == '_' || digit) {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+        }
+
+        // a lone `$` is kept as is
+        if name.is_empty() {
+            expanded.push('$');
+            continue;
+        }
+        match env::var(&name) {
+            Ok(value) => expanded.push_str(&value),
+            Err(_) => unset.push(name),
+        }
+    }
+    expanded
+}
+
+/// Pipes options into the menu command and returns its output and how it
+/// exited. Variables are expanded in every word of the command; with
+/// verbose, unset ones are reported on stderr.
+pub fn exec_dmenu(exec: &str, options: &str, verbose: bool)
+                  -> Result<(String, ExitStatus), io::Error> {
+    use std::io::prelude::*;
+    let (program, args) = split_exec_args(exec)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty dmenu command"))?;
+
+    let mut unset = Vec::new();
+    let program = expand_env(&program, &mut unset);
+    let args = args.iter().map(|a| expand_env(a, &mut unset)).collect::<Vec<_>>();
+    if verbose {
+        for name in unset {
+            eprintln!("warning: ${} is not set in the menu command", name);
+        }
+    }
+    let context = |what: &str, e: io::Error| {
+        io::Error::new(e.kind(), format!("failed to {} '{}': {}", what, program, e))
+    };
+
+    let mut cmd = Command::new(&program)
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| context("launch", e))?;
+
+    // stdin is dropped at the end of the block so the menu sees EOF
+    if let Some(mut stdin) = cmd.stdin.take() {
+        stdin.write_all(options.as_bytes()).map_err(|e| context("write options to", e))?;
+    }
+
+    let mut s = String::new();
+    if let Some(mut stdout) = cmd.stdout.take() {
+        stdout.read_to_string(&mut s).map_err(|e| context("read selection from", e))?;
+    }
+    let status = cmd.wait().map_err(|e| context("wait for", e))?;
+
+    Ok((s, status))
+}
+
+/// Whether the menu command can be run: there's a display for it to open
+/// on and its program is installed
+pub fn menu_available(exec: &str) -> bool {
+    if env::var_os("DISPLAY").is_none() && env::var_os("WAYLAND_DISPLAY").is_none() {
+        return false;
+    }
+    let program = match split_exec_args(exec) {
+        Some((program, _)) => expand_env(&program, &mut Vec::new()),
+        None => return false,
+    };
+    if program.contains('/') {
+        return Path::new(&program).is_file();
+    }
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(&program).is_file()))
+}
+
+// The socket given, else Sway's (same IPC protocol), else i3's; with none
+// of them i3 gets asked. Sway sets I3SOCK as well, SWAYSOCK is the one
+// telling it's there.
+fn socket_path(explicit: Option<&str>, swaysock: Option<String>, i3sock: Option<String>)
+               -> Option<String> {
+    explicit.map(|s| s.to_owned()).or(swaysock).or(i3sock)
+}
+
+fn env_socket_path(socket: Option<&str>) -> Option<String> {
+    socket_path(socket, env::var("SWAYSOCK").ok(), env::var("I3SOCK").ok())
+}
+
+fn connect_error<E: std::fmt::Display>(path: Option<String>, e: E) -> Box<dyn Error> {
+    match path {
+        Some(path) => format!("could not connect to i3 socket '{}': {}", path, e),
+        None => format!("could not connect to i3: is i3 (or sway) running? ({})", e),
+    }.into()
+}
+
+/// Calls attempt until it succeeds, up to retries more times after the first
+/// failure, sleeping delay (doubled every time, up to max_delay) in between.
+/// The last error is returned.
+fn retry<T, E, F>(retries: u32, delay: Duration, max_delay: Duration, mut attempt: F)
+                  -> Result<T, E>
+    where F: FnMut() -> Result<T, E>
+{
+    let mut delay = delay;
+    let mut failures = 0;
+    loop {
+        match attempt() {
+            Ok(value) => return Ok(value),
+            Err(e) => if failures >= retries {
+                return Err(e);
+            },
+        }
+        failures += 1;
+        thread::sleep(delay);
+        delay = next_delay(delay, max_delay);
+    }
+}
+
+// Doubles delay, up to max
+fn next_delay(delay: Duration, max: Duration) -> Duration {
+    cmp::min(delay.checked_mul(2).unwrap_or(max), max)
+}
+
+fn retry_delay() -> Duration {
+    Duration::from_millis(CONNECT_RETRY_DELAY_MS)
+}
+
+fn max_retry_delay() -> Duration {
+    Duration::from_millis(MAX_CONNECT_RETRY_DELAY_MS)
+}
+
+/// Checks that an explicitly given socket path is a socket at all, a typo
+/// is better reported as such than as a failure to connect
+pub fn check_socket(path: &str) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match fs::metadata(path) {
+        Ok(ref meta) if meta.file_type().is_socket() => Ok(()),
+        Ok(_) => Err(format!("'{}' is not a socket", path).into()),
+        Err(e) => Err(format!("could not use i3 socket '{}': {}", path, e).into()),
+    }
+}
+
+/// Connects to i3, or to Sway or an explicit socket, retrying a few times in
+/// case i3 is restarting
+pub fn connect(socket: Option<&str>, retries: u32) -> Result<I3Connection, Box<dyn Error>> {
+    if let Some(socket) = socket {
+        check_socket(socket)?;
+    }
+    let path = env_socket_path(socket);
+    let attempt = || match path {
+        Some(ref path) => I3Connection::connect_to(path),
+        None => I3Connection::connect(),
+    };
+    retry(retries, retry_delay(), max_retry_delay(), attempt)
+        .map_err(|e| connect_error(path, e))
+}
+
+// keyed by container id, native Wayland windows have no X id
+fn mru_event(event: &WindowEvent) -> Option<MruEvent> {
+    match event.change {
+        WindowChange::Focus => Some(MruEvent::Focus(event.container.id)),
+        WindowChange::Close => Some(MruEvent::Close(event.container.id)),
+        WindowChange::Other => None,
+    }
+}
+
+/// Exits the process on SIGTERM or SIGINT, though not while saving holds its
+/// lock. Both signals are blocked in the calling thread, so this must be
+/// called before any other thread is started.
+fn exit_on_signal(saving: Arc<Mutex<()>>) -> Result<(), Box<dyn Error>> {
+    let mut signals: libc::sigset_t = unsafe { mem::zeroed() };
+    let blocked = unsafe {
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+        libc::sigaddset(&mut signals, libc::SIGINT);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, ptr::null_mut())
+    };
+    if blocked != 0 {
+        return Err("could not block SIGTERM".into());
+    }
+
+    // threads inherit the mask, this one is the only taker of the signals
+    thread::spawn(move || {
+        let mut signal = 0;
+        unsafe { libc::sigwait(&signals, &mut signal) };
+        let _saved = saving.lock();
+        process::exit(0);
+    });
+    Ok(())
+}
+
+/// Follows window focus events and keeps the state file at path up to date
+/// for `sort_by_mru`. Runs until i3 can't be reached anymore, or until it is
+/// sent SIGTERM, which doesn't interrupt a save; a restart of i3 drops the
+/// event socket, which is reconnected after a short pause.
+pub fn run_daemon(socket: Option<&str>, path: &Path, retries: u32, verbose: bool)
+                  -> Result<(), Box<dyn Error>> {
+    if let Some(socket) = socket {
+        check_socket(socket)?;
+    }
+    let saving = Arc::new(Mutex::new(()));
+    exit_on_signal(saving.clone())?;
+    let mut mru = load_mru(path)?;
+
+    loop {
+        let socket_path = env_socket_path(socket);
+        let attempt = || match socket_path {
+            Some(ref path) => I3EventListener::connect_to(path),
+            None => I3EventListener::connect(),
+        };
+        let mut listener = retry(retries, retry_delay(), max_retry_delay(), attempt)
+            .map_err(|e| connect_error(socket_path, e))?;
+
+        loop {
+            let event = match listener.next_event() {
+                Ok(event) => event,
+                Err(e) => {
+                    if verbose {
+                        eprintln!("event stream closed: {}", e);
+                    }
+                    break;
+                }
+            };
+            if let Some(event) = mru_event(&event) {
+                update_mru(&mut mru, event, MRU_SIZE);
+                // the lock only guards the file, a poisoned one is as good
+                let _saving = saving.lock().unwrap_or_else(|e| e.into_inner());
+                save_mru(path, &mru)?;
+            }
+        }
+
+        // give a restarting i3 time to open its socket again
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// How commands are sent to i3
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SendOptions {
+    /// Trace commands and i3's replies on stderr
+    pub verbose: bool,
+    /// Write commands out instead of sending them
+    pub dry_run: bool,
+}
+
+/// Runs an i3 command, or writes it to out on a dry run
+pub fn send_command(conn: &mut dyn Ipc, command: &str, options: SendOptions,
+                    out: &mut dyn io::Write) -> Result<(), Box<dyn Error>> {
+    if options.dry_run {
+        writeln!(out, "{}", command)?;
+        return Ok(());
+    }
+    if options.verbose {
+        eprintln!("i3 command: {}", command);
+    }
+    let reply = conn.command(command)?;
+    if options.verbose {
+        eprintln!("i3 reply: {:?}", reply);
+    }
+    command_result(command, &reply)
+}
+
+/// An error holding i3's messages if any part of command failed
+fn command_result(command: &str, reply: &reply::Command) -> Result<(), Box<dyn Error>> {
+    let errors = reply.outcomes.iter()
+        .filter(|o| !o.success)
+        .map(|o| o.error.to_owned().unwrap_or_else(|| "unknown error".to_owned()))
+        .collect::<Vec<_>>();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("i3 could not run '{}': {}", command, errors.join("; ")).into())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::process;
+
+    fn window(id: i32, class: Option<&str>, name: &str) -> Window {
+        Window {
+            id: Some(id),
+            con_id: i64::from(id) + 1000,
+            name: name.to_owned(),
+            class_name: class.map(|c| c.to_owned()),
+            instance: None,
+            focused: false,
+            urgent: false,
+            floating: false,
+            workspace: None,
+            output: None,
+            marks: Vec::new(),
+            rect: (0, 0, 800, 600),
+            pid: None,
+            icon: None,
+        }
+    }
+
+    #[test]
+    fn focus_selects_the_window_by_id() {
+        let w = window(7, Some("URxvt"), "~");
+        assert_eq!(focus_command(&w, false), "[id=\"7\"] focus");
+    }
+
+    #[test]
+    fn max_class_name_size_of_no_windows_is_0() {
+        assert_eq!(max_class_name_size(&[]), 0);
+    }
+
+    #[test]
+    fn max_class_name_size_without_classes_is_0() {
+        let windows = [window(1, None, "a"), window(2, None, "b")];
+        assert_eq!(max_class_name_size(&windows), 0);
+    }
+
+    #[test]
+    fn windows_with_the_same_label_are_both_selectable() {
+        let windows = vec![window(1, Some("URxvt"), "~"), window(2, Some("URxvt"), "~")];
+        let layout = Layout { padding: 10, ..Layout::default() };
+        assert_eq!(windows[0].pad_format(&layout), windows[1].pad_format(&layout));
+
+        let mut mapping: Mapping = Vec::new();
+        for w in windows {
+            push_unique(&mut mapping, w.pad_format(&layout), Box::new(w));
+        }
+        let selected = mapping.iter()
+            .map(|entry| find_selection(&mapping, &entry.0).map(|s| s.to_select_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(selected, vec![Some("[id=\"1\"]".to_owned()), Some("[id=\"2\"]".to_owned())]);
+    }
+
+    #[test]
+    fn floating_windows_are_listed() {
+        let mut tiled = empty_node(reply::NodeType::Con, 1, Some("~".to_owned()));
+        tiled.window = Some(1);
+        let mut popup = empty_node(reply::NodeType::Con, 2, Some("Volume Control".to_owned()));
+        popup.window = Some(2);
+        let mut floating = empty_node(reply::NodeType::FloatingCon, 3, None);
+        floating.nodes.push(popup);
+        let mut workspace = empty_node(reply::NodeType::Workspace, 4, Some("1".to_owned()));
+        workspace.nodes.push(tiled);
+        workspace.floating_nodes.push(floating);
+
+        let leaves = flatten_nodes(&workspace, Location::default());
+        let ids = leaves.iter().map(|&(_, n)| n.window).collect::<Vec<_>>();
+        assert_eq!(ids, vec![Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn socket_flag_then_sway_then_i3() {
+        let sway = || Some("/run/sway.sock".to_owned());
+        let i3 = || Some("/run/i3.sock".to_owned());
+        assert_eq!(socket_path(Some("/tmp/nested"), sway(), i3()), Some("/tmp/nested".to_owned()));
+        assert_eq!(socket_path(None, sway(), i3()), sway());
+        assert_eq!(socket_path(None, None, i3()), i3());
+        assert_eq!(socket_path(None, None, None), None);
+    }
+
+    #[test]
+    fn wayland_windows_take_their_app_id_as_class() {
+        let mut node = empty_node(reply::NodeType::Con, 42, Some("~/src".to_owned()));
+        node.app_id = Some("foot".to_owned());
+        assert!(filter_node(&node, &Ignores::new(true)));
+
+        let w = window_from_node(&node, Location::default(), None).unwrap();
+        assert_eq!(w.class_name, Some("foot".to_owned()));
+        assert_eq!(w.id, None);
+        assert_eq!(w.to_select_string(), "[con_id=\"42\"]");
+    }
+
+    #[test]
+    fn x_class_wins_over_app_id() {
+        let mut node = empty_node(reply::NodeType::Con, 42, Some("~/src".to_owned()));
+        node.window = Some(7);
+        node.class_name = Some("URxvt".to_owned());
+        node.app_id = Some("urxvt".to_owned());
+
+        let w = window_from_node(&node, Location::default(), None).unwrap();
+        assert_eq!(w.class_name, Some("URxvt".to_owned()));
+        assert_eq!(w.to_select_string(), "[id=\"7\"]");
+    }
+
+    #[test]
+    fn split_containers_are_not_windows() {
+        let node = empty_node(reply::NodeType::Con, 42, Some("split".to_owned()));
+        assert!(!filter_node(&node, &Ignores::new(true)));
+    }
+
+    #[test]
+    fn dmenu_flag_then_config_then_default() {
+        let config = Config { dmenu_command: Some("rofi -dmenu".to_owned()), ..Config::default() };
+        assert_eq!(dmenu_command(Some("bemenu"), &config, DEFAULT_DMENU_COMMAND), "bemenu");
+        assert_eq!(dmenu_command(None, &config, DEFAULT_DMENU_COMMAND), "rofi -dmenu");
+        assert_eq!(dmenu_command(None, &Config::default(), DEFAULT_ROFI_COMMAND),
+                   DEFAULT_ROFI_COMMAND);
+    }
+
+    #[test]
+    fn missing_config_file_means_defaults() {
+        let config = load_config(Path::new("/nonexistent/quickswitch-i3/config.toml")).unwrap();
+        assert!(config.dmenu_command.is_none());
+        let mut ignores = Ignores::new(true);
+        ignores.extend(&config.ignore_names, &config.ignore_classes);
+        assert_eq!(ignores.names, ["__i3_scratch"]);
+        assert_eq!(ignores.classes, ["i3bar"]);
+    }
+
+    #[test]
+    fn config_file_is_loaded() {
+        let path = env::temp_dir().join(format!("quickswitch-i3-config-{}.toml", process::id()));
+        std::fs::write(&path, "dmenu_command = \"rofi -dmenu\"\nignore_classes = [\"Conky\"]\n").unwrap();
+        let config = load_config(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.dmenu_command.as_deref(), Some("rofi -dmenu"));
+        assert_eq!(config.ignore_classes, ["Conky"]);
+    }
+
+    #[test]
+    fn unparsable_config_file_is_an_error() {
+        let path = env::temp_dir().join(format!("quickswitch-i3-bad-config-{}.toml", process::id()));
+        std::fs::write(&path, "dmenu_command = [").unwrap();
+        let err = load_config(&path).unwrap_err().to_string();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(err.starts_with(&format!("could not parse config file {}: ", path.display())), "{}", err);
+    }
+
+    fn x_window(id: i32, class: &str, name: &str) -> reply::Node {
+        let mut node = empty_node(reply::NodeType::Con, i64::from(id), Some(name.to_owned()));
+        node.window = Some(id);
+        node.class_name = Some(class.to_owned());
+        node
+    }
+
+    #[test]
+    fn ignored_classes_are_hidden() {
+        let firefox = x_window(1, "Firefox", "News");
+        let mut ignores = Ignores::new(true);
+        assert!(filter_node(&firefox, &ignores));
+
+        ignores.extend(&[], &["Firefox".to_owned()]);
+        assert!(!filter_node(&firefox, &ignores));
+        assert!(filter_node(&x_window(2, "URxvt", "~"), &ignores));
+    }
+
+    #[test]
+    fn default_ignores_can_be_dropped() {
+        let bar = x_window(1, "i3bar", "bar");
+        assert!(!filter_node(&bar, &Ignores::new(true)));
+        assert!(filter_node(&bar, &Ignores::new(false)));
+    }
+
+    #[test]
+    fn filters_match_titles_classes_or_either() {
+        let windows = [
+            window(1, Some("Firefox"), "Rust docs"),
+            window(2, Some("URxvt"), "vim docs.txt"),
+            window(3, Some("Emacs"), "firefox.el"),
+        ];
+        let kept = |filter: WindowFilter| {
+            windows.iter().filter(|w| filter.matches(w)).map(|w| w.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        let re = |pattern: &str| Some(Regex::new(pattern).unwrap());
+
+        assert_eq!(kept(WindowFilter { title: re("docs"), ..WindowFilter::default() }),
+                   vec!["Rust docs", "vim docs.txt"]);
+        assert_eq!(kept(WindowFilter { class: re("^(Firefox|Emacs)$"), ..WindowFilter::default() }),
+                   vec!["Rust docs", "firefox.el"]);
+        assert_eq!(kept(WindowFilter { any: re("(?i)firefox"), ..WindowFilter::default() }),
+                   vec!["Rust docs", "firefox.el"]);
+        assert_eq!(kept(WindowFilter { title: re("docs"), class: re("URxvt"), any: None }),
+                   vec!["vim docs.txt"]);
+        assert_eq!(kept(WindowFilter::default()).len(), 3);
+    }
+
+    #[test]
+    fn the_last_word_of_a_menu_command_is_kept() {
+        assert_eq!(split_exec_args("dmenu"), Some(("dmenu".to_owned(), vec![])));
+        assert_eq!(split_exec_args("dmenu  -b -i "),
+                   Some(("dmenu".to_owned(), vec!["-b".to_owned(), "-i".to_owned()])));
+        assert_eq!(split_exec_args(""), None);
+        assert_eq!(split_exec_args("   "), None);
+    }
+
+    #[test]
+    fn missing_menu_program_is_an_error() {
+        let err = exec_dmenu("quickswitch-i3-no-such-menu -i", "a\nb", false).unwrap_err();
+        assert!(err.to_string().contains("failed to launch 'quickswitch-i3-no-such-menu'"),
+                "{}", err);
+    }
+
+    #[test]
+    fn empty_menu_command_is_an_error() {
+        assert!(exec_dmenu("  ", "a\nb", false).is_err());
+    }
+
+    fn mapping(windows: Vec<Window>) -> Mapping {
+        mapping_with(windows, &Layout::default())
+    }
+
+    fn mapping_with(windows: Vec<Window>, layout: &Layout) -> Mapping {
+        windows.into_iter().map(|w| (w.label(layout), Some(Box::new(w) as Box<dyn Selectable>)))
+            .collect()
+    }
+
+    #[test]
+    fn rofi_index_selects_by_position() {
+        let mapping = mapping(vec![window(1, Some("URxvt"), "~"), window(2, Some("URxvt"), "~")]);
+        let (selected, text) = resolve_selection(&mapping, "1 ~\n", true);
+        assert_eq!(selected.map(|s| s.to_select_string()), Some("[id=\"2\"]".to_owned()));
+        assert_eq!(text, "~");
+
+        let (selected, text) = resolve_selection(&mapping, "-1 new workspace", true);
+        assert!(selected.is_none());
+        assert_eq!(text, "new workspace");
+
+        assert!(resolve_selection(&mapping, "5 ~", true).0.is_none());
+    }
+
+    #[test]
+    fn long_titles_are_cut_with_an_ellipsis() {
+        assert_eq!(truncate("Mozilla Firefox", 8), "Mozilla…");
+        assert_eq!(truncate("Mozilla Firefox", 15), "Mozilla Firefox");
+        assert_eq!(truncate("Mozilla Firefox", 0), "");
+
+        let layout = Layout { max_title_width: Some(8), ..Layout::default() };
+        assert_eq!(window(1, None, "Mozilla Firefox").pad_format(&layout), "Mozilla…");
+    }
+
+    #[test]
+    fn templates_cut_long_titles_in_the_menu_only() {
+        let template = "{class}: {name}".parse::<Template>().unwrap();
+        let layout = Layout {
+            max_title_width: Some(5),
+            template: Some(template.clone()),
+            ..Layout::default()
+        };
+        let w = window(1, Some("Gvim"), "Crème brûlée");
+        assert_eq!(w.label(&layout), "Gvim: Crèm…");
+        assert_eq!(template.render(&w), "Gvim: Crème brûlée");
+    }
+
+    #[test]
+    fn accented_titles_are_cut_between_characters() {
+        assert_eq!(truncate("Crème brûlée", 6), "Crème…");
+        assert_eq!(truncate("Crème brûlée", 11), "Crème brûl…");
+        assert_eq!(truncate("Crème brûlée", 12), "Crème brûlée");
+    }
+
+    #[test]
+    fn titles_line_up_after_wide_class_names() {
+        let windows = vec![
+            window(1, Some("Émacs"), "init.el"),
+            window(2, Some("微信"), "chat"),
+            window(3, Some("XTerm"), "~"),
+        ];
+        assert_eq!(max_class_name_size(&windows), 5);
+
+        let layout = Layout { padding: max_class_name_size(&windows) + 1, ..Layout::default() };
+        let title_columns = windows.iter()
+            .map(|w| w.pad_format(&layout).width() - w.name.width())
+            .collect::<Vec<_>>();
+        assert_eq!(title_columns, vec![6, 6, 6]);
+        assert_eq!(windows[1].pad_format(&layout), "微信  chat");
+    }
+
+    #[test]
+    fn menu_options_do_not_depend_on_the_input_order() {
+        let windows = || vec![
+            window(1, Some("URxvt"), "~"),
+            window(2, Some("Firefox"), "News"),
+            window(3, Some("Emacs"), "main.rs"),
+        ];
+        let options = |mut windows: Vec<Window>| {
+            sort_windows(&mut windows, SortOrder::Class);
+            let layout = Layout { padding: max_class_name_size(&windows) + 1, ..Layout::default() };
+            windows.iter().map(|w| w.pad_format(&layout)).collect::<Vec<_>>().join("\n")
+        };
+
+        let mut reversed = windows();
+        reversed.reverse();
+        let first = options(windows());
+        assert_eq!(first, "Emacs   main.rs\nFirefox News\nURxvt   ~");
+        assert_eq!(options(windows()), first);
+        assert_eq!(options(reversed), first);
+    }
+
+    #[test]
+    fn workspaces_sort_by_number_name_or_output() {
+        let on = |name: &str, num: i32, output: &str| {
+            Workspace { output: output.to_owned(), ..workspace(name, num) }
+        };
+        let workspaces = vec![
+            on("mail", -1, "HDMI-1"),
+            on("10", 10, "eDP-1"),
+            on("2", 2, "HDMI-1"),
+            on("chat", -1, "eDP-1"),
+            on("1", 1, "eDP-1"),
+        ];
+        let sorted = |order| {
+            let mut workspaces = workspaces.clone();
+            sort_workspaces(&mut workspaces, order);
+            workspaces.into_iter().map(|w| w.name).collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(WorkspaceOrder::Num), vec!["1", "2", "10", "chat", "mail"]);
+        assert_eq!(sorted(WorkspaceOrder::Name), vec!["1", "10", "2", "chat", "mail"]);
+        assert_eq!(sorted(WorkspaceOrder::Output), vec!["2", "mail", "1", "10", "chat"]);
+    }
+
+    fn names(windows: &[Window]) -> Vec<&str> {
+        windows.iter().map(|w| w.name.as_str()).collect()
+    }
+
+    #[test]
+    fn windows_sort_by_title_or_by_class() {
+        let windows = || vec![
+            window(1, Some("URxvt"), "~"),
+            window(2, Some("Firefox"), "News"),
+            window(3, Some("URxvt"), "htop"),
+            window(4, Some("Emacs"), "main.rs"),
+        ];
+
+        let mut sorted = windows();
+        sort_windows(&mut sorted, SortOrder::Alpha);
+        assert_eq!(names(&sorted), vec!["News", "htop", "main.rs", "~"]);
+
+        let mut sorted = windows();
+        sort_windows(&mut sorted, SortOrder::Class);
+        assert_eq!(names(&sorted), vec!["main.rs", "News", "htop", "~"]);
+
+        let mut sorted = windows();
+        sort_windows(&mut sorted, SortOrder::Mru);
+        assert_eq!(names(&sorted), names(&windows()));
+    }
+
+    #[test]
+    fn focused_and_urgent_windows_are_marked_and_still_selectable() {
+        let mut current = window(1, Some("URxvt"), "~");
+        current.focused = true;
+        current.urgent = true;
+        let windows = vec![current, window(2, Some("Emacs"), "main.rs")];
+        let layout = Layout {
+            padding: max_class_name_size(&windows) + 1,
+            marker_focused: Some("*".to_owned()),
+            marker_urgent: Some("!".to_owned()),
+            ..Layout::default()
+        };
+        assert_eq!(windows[0].pad_format(&layout), "*! URxvt ~");
+        assert_eq!(windows[1].pad_format(&layout), "   Emacs main.rs");
+
+        let mapping = mapping_with(windows, &layout);
+        let selected = find_selection(&mapping, "*! URxvt ~").map(|s| s.to_select_string());
+        assert_eq!(selected, Some("[id=\"1\"]".to_owned()));
+    }
+
+    #[test]
+    fn windows_know_their_workspace() {
+        let mut first = empty_node(reply::NodeType::Workspace, 10, Some("1".to_owned()));
+        first.nodes.push(x_window(1, "URxvt", "~"));
+        first.nodes.push(x_window(3, "XTerm", "top"));
+        let mut mail = empty_node(reply::NodeType::Workspace, 20, Some("2: mail".to_owned()));
+        mail.nodes.push(x_window(2, "Thunderbird", "Inbox"));
+        let mut output = empty_node(reply::NodeType::Output, 30, Some("eDP-1".to_owned()));
+        output.nodes.push(first);
+        output.nodes.push(mail);
+
+        let workspaces = flatten_nodes(&output, Location::default()).into_iter()
+            .filter_map(|(location, n)| window_from_node(n, location, None))
+            .map(|w| (w.name, w.workspace))
+            .collect::<Vec<_>>();
+        assert_eq!(workspaces, vec![("~".to_owned(), Some("1".to_owned())),
+                                    ("top".to_owned(), Some("1".to_owned())),
+                                    ("Inbox".to_owned(), Some("2: mail".to_owned()))]);
+    }
+
+    #[test]
+    fn group_headers_are_shown_but_not_selectable() {
+        let in_workspace = |id, name: &str, workspace: &str| {
+            let mut w = window(id, None, name);
+            w.workspace = Some(workspace.to_owned());
+            w
+        };
+        let windows = vec![in_workspace(1, "~", "1"), in_workspace(2, "Inbox", "2"),
+                           in_workspace(3, "top", "1")];
+        let layout = Layout::default();
+
+        let mut mapping: Mapping = Vec::new();
+        for (workspace, windows) in group_by_workspace(windows) {
+            push_header(&mut mapping, &format!("Workspace {}", workspace.unwrap_or_default()));
+            for w in windows {
+                push_unique(&mut mapping, w.pad_format(&layout), Box::new(w));
+            }
+        }
+
+        let options = mapping.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>().join("\n");
+        assert_eq!(options, "── Workspace 1 ──\n~\ntop\n── Workspace 2 ──\nInbox");
+        assert!(find_selection(&mapping, "── Workspace 2 ──").is_none());
+        assert_eq!(mapping.iter().filter_map(entry_selectable).count(), 3);
+    }
+
+    #[test]
+    fn templates_substitute_placeholders() {
+        let mut w = window(7, Some("Firefox"), "News");
+        w.workspace = Some("1".to_owned());
+        w.marks = vec!["a".to_owned(), "b".to_owned()];
+        let render = |format: &str| format.parse::<Template>().unwrap().render(&w);
+
+        assert_eq!(render("{class} — {name}"), "Firefox — News");
+        assert_eq!(render("{id}/{con_id} on {workspace} [{mark}]"), "7/1007 on 1 [a,b]");
+        assert_eq!(render("<{name}>"), "<News>");
+    }
+
+    #[test]
+    fn bad_templates_are_rejected_up_front() {
+        assert_eq!("{class} {title}".parse::<Template>(),
+                   Err("unknown placeholder '{title}' in format '{class} {title}'".to_owned()));
+        assert!("{class".parse::<Template>().is_err());
+    }
+
+    #[test]
+    fn instance_is_kept_apart_from_the_class() {
+        let mut node = x_window(7, "Firefox", "News");
+        node.instance = Some("work".to_owned());
+
+        let w = window_from_node(&node, Location::default(), None).unwrap();
+        assert_eq!(w.class_name, Some("Firefox".to_owned()));
+        assert_eq!(w.instance, Some("work".to_owned()));
+
+        let layout = Layout { padding: 8, instance_padding: Some(5), ..Layout::default() };
+        assert_eq!(w.label(&layout), "Firefox work News");
+        let other = window(8, Some("Firefox"), "News");
+        assert_eq!(other.label(&layout), "Firefox      News");
+    }
+
+    #[test]
+    fn move_to_workspace_quotes_the_target() {
+        let w = window(7, Some("URxvt"), "~");
+        assert_eq!(move_to_workspace_command(&w, "web site"),
+                   "[id=\"7\"] move workspace \"web site\"");
+        assert_eq!(move_to_workspace_command(&w, "3"), "[id=\"7\"] move workspace \"3\"");
+    }
+
+    #[test]
+    fn move_to_output_names_the_output() {
+        let w = window(7, Some("URxvt"), "~");
+        assert_eq!(move_to_output_command(&w, "HDMI-1"), "[id=\"7\"] move output \"HDMI-1\"");
+        assert_eq!(move_to_output_command(&w, "Dell \"U2415\""),
+                   "[id=\"7\"] move output \"Dell \\\"U2415\\\"\"");
+    }
+
+    fn workspace(name: &str, num: i32) -> Workspace {
+        Workspace {
+            name: name.to_owned(),
+            num: num,
+            output: "eDP-1".to_owned(),
+            focused: false,
+            urgent: false,
+            window_count: None,
+        }
+    }
+
+    #[test]
+    fn rename_quotes_both_names() {
+        assert_eq!(rename_workspace_command(&workspace("1", 1), "1: web site"),
+                   "rename workspace \"1\" to \"1: web site\"");
+        assert_eq!(rename_workspace_command(&workspace("my \"stuff\"", -1), "stuff"),
+                   "rename workspace \"my \\\"stuff\\\"\" to \"stuff\"");
+    }
+
+    #[test]
+    fn numeric_new_workspaces_go_by_number() {
+        assert_eq!(new_workspace_command("5"), "workspace number 5");
+        assert_eq!(new_workspace_command("mail"), "workspace \"mail\"");
+        assert_eq!(new_workspace_command("5: mail"), "workspace \"5: mail\"");
+    }
+
+    fn split(command: &str) -> Option<(String, Vec<String>)> {
+        split_exec_args(command)
+    }
+
+    fn args(program: &str, args: &[&str]) -> Option<(String, Vec<String>)> {
+        Some((program.to_owned(), args.iter().map(|a| a.to_string()).collect()))
+    }
+
+    #[test]
+    fn plain_words_are_split_on_whitespace() {
+        assert_eq!(split("dmenu -b -i"), args("dmenu", &["-b", "-i"]));
+        assert_eq!(split("  dmenu\t-b   -i  "), args("dmenu", &["-b", "-i"]));
+    }
+
+    #[test]
+    fn quotes_group_words() {
+        assert_eq!(split("rofi -dmenu -p \"pick a window\""),
+                   args("rofi", &["-dmenu", "-p", "pick a window"]));
+        assert_eq!(split("rofi -p 'pick \"one\"'"), args("rofi", &["-p", "pick \"one\""]));
+        assert_eq!(split("dmenu -p \"\""), args("dmenu", &["-p", ""]));
+        assert_eq!(split("dmenu -p a\"b c\"d"), args("dmenu", &["-p", "ab cd"]));
+    }
+
+    #[test]
+    fn backslashes_escape() {
+        assert_eq!(split("dmenu -p pick\\ one"), args("dmenu", &["-p", "pick one"]));
+        assert_eq!(split("dmenu -p \"say \\\"hi\\\"\""), args("dmenu", &["-p", "say \"hi\""]));
+        assert_eq!(split("dmenu -p \"a\\\\b\""), args("dmenu", &["-p", "a\\b"]));
+        assert_eq!(split("dmenu -p \"a\\nb\""), args("dmenu", &["-p", "a\\nb"]));
+        assert_eq!(split("dmenu -p 'a\\b'"), args("dmenu", &["-p", "a\\b"]));
+    }
+
+    #[test]
+    fn prompts_reach_the_menu_as_one_argument() {
+        assert_eq!(split(&with_prompt("rofi -dmenu", "pick a window")),
+                   args("rofi", &["-dmenu", "-p", "pick a window"]));
+        assert_eq!(split(&with_prompt("dmenu", "say \"hi\"")),
+                   args("dmenu", &["-p", "say \"hi\""]));
+
+        // the menu prints the argument following -p
+        let menu = DmenuBackend {
+            command: "sh -c 'cat >/dev/null; printf %s \"$2\"' sh".to_owned(),
+            indexed: false,
+            verbose: false,
+            max_lines: None,
+            lines_flag: LINES_FLAG,
+        };
+        assert_eq!(menu.choose("a\nb", Some("pick a window")).unwrap().text, "pick a window");
+    }
+
+    // What flatten_nodes did before it had a stack of its own
+    fn recursive_leaves<'a>(node: &'a reply::Node, location: Location<'a>)
+                            -> Vec<(Location<'a>, &'a reply::Node)> {
+        let mut location = location;
+        if let reply::NodeType::Workspace = node.nodetype {
+            location.workspace = node.name.as_deref();
+        }
+        if node.nodes.is_empty() && node.floating_nodes.is_empty() {
+            return vec![(location, node)];
+        }
+        focus_ordered(node).into_iter()
+            .flat_map(|(floating, n)| {
+                let floating = location.floating || floating;
+                recursive_leaves(n, Location { floating: floating, ..location })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flattening_visits_leaves_like_the_recursive_walk() {
+        let leaf = |id| empty_node(reply::NodeType::Con, id, Some(format!("w{}", id)));
+        let container = |id, children: Vec<reply::Node>, focus: Vec<i64>| {
+            let mut node = empty_node(reply::NodeType::Con, id, None);
+            node.nodes = children;
+            node.focus = focus;
+            node
+        };
+
+        let inner = container(10, vec![leaf(1), leaf(2), leaf(3)], vec![3, 1, 2]);
+        let middle = container(11, vec![leaf(4), inner, leaf(5)], vec![10]);
+        let mut one = empty_node(reply::NodeType::Workspace, 20, Some("1".to_owned()));
+        one.nodes = vec![middle, container(12, vec![leaf(6), leaf(7)], vec![])];
+        one.floating_nodes = vec![container(13, vec![leaf(8)], vec![])];
+        one.focus = vec![13, 11];
+        let mut two = empty_node(reply::NodeType::Workspace, 21, Some("2".to_owned()));
+        two.nodes = vec![container(14, vec![container(15, vec![leaf(9)], vec![])], vec![])];
+        let mut output = empty_node(reply::NodeType::Output, 30, Some("eDP-1".to_owned()));
+        output.nodes = vec![one, two];
+        let mut root = empty_node(reply::NodeType::Root, 0, Some("root".to_owned()));
+        root.nodes = vec![output];
+
+        let walk = |leaves: Vec<(Location, &reply::Node)>| {
+            leaves.into_iter()
+                .map(|(l, n)| (n.id, l.workspace.map(|s| s.to_owned())))
+                .collect::<Vec<_>>()
+        };
+        let leaves = walk(flatten_nodes(&root, Location::default()));
+        assert_eq!(leaves, walk(recursive_leaves(&root, Location::default())));
+        let ids = leaves.iter().map(|l| l.0).collect::<Vec<_>>();
+        assert_eq!(ids, vec![8, 3, 1, 2, 4, 5, 6, 7, 9]);
+    }
+
+    #[test]
+    fn stream_menu_writes_the_options_and_reads_the_choice() {
+        let layout = Layout { template: Some("{name}".parse().unwrap()), ..Layout::default() };
+        let mapping = mapping_with(vec![window(1, None, "~"), window(2, None, "top")], &layout);
+        let menu = StreamBackend::new(io::Cursor::new("top\n"), Vec::new());
+
+        let (selected, text) = choose(&menu, &mapping, Some("focus")).unwrap();
+        assert_eq!(selected.map(|s| s.to_select_string()), Some("[id=\"2\"]".to_owned()));
+        assert_eq!(text, "top");
+        assert_eq!(menu.writer.into_inner(), b"~\ntop\n");
+    }
+
+    #[test]
+    fn stream_menu_at_end_of_input_selects_nothing() {
+        let mapping = mapping(vec![window(1, None, "~")]);
+        let menu = StreamBackend::new(io::Cursor::new(""), Vec::new());
+        let (selected, text) = choose(&menu, &mapping, None).unwrap();
+        assert!(selected.is_none());
+        assert_eq!(text, "");
+    }
+
+    #[test]
+    fn history_keeps_the_latest_selections_once() {
+        let mut history = Vec::new();
+        for entry in &["a", "b", "c", "a", "d"] {
+            record_history(&mut history, entry, 3);
+        }
+        assert_eq!(history, vec!["d", "a", "c"]);
+    }
+
+    #[test]
+    fn history_brings_past_selections_first() {
+        let mut mapping = mapping(vec![
+            window(1, Some("Firefox"), "News"),
+            window(2, Some("URxvt"), "~"),
+            window(3, Some("Emacs"), "main.rs"),
+        ]);
+        push_header(&mut mapping, "Workspaces");
+        // a title changing since doesn't matter, the window is the same
+        mapping[2].0 = "Emacs lib.rs".to_owned();
+
+        let history = ["[id=\"3\"]", "[id=\"9\"]", "[id=\"2\"]"].iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+        boost_history(&mut mapping, &history);
+        let selects = mapping.iter()
+            .map(|e| entry_selectable(e).map(|s| s.to_select_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(selects, vec![Some("[id=\"3\"]".to_owned()), Some("[id=\"2\"]".to_owned()),
+                                 Some("[id=\"1\"]".to_owned()), None]);
+    }
+
+    #[test]
+    fn history_files_round_trip() {
+        let dir = env::temp_dir().join(format!("quickswitch-i3-lib-history-{}", process::id()));
+        let path = history_path(&dir, "windows");
+        assert_eq!(load_history(&path).unwrap(), Vec::<String>::new());
+
+        let history = vec!["[id=\"3\"]".to_owned(), "[id=\"1\"]".to_owned()];
+        save_history(&path, &history).unwrap();
+        assert_eq!(load_history(&path).unwrap(), history);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_history_is_an_error() {
+        // a directory can be opened, not read
+        let e = load_history(Path::new("/")).unwrap_err();
+        assert!(e.to_string().starts_with("could not read history file /:"), "{}", e);
+        let e = save_history(&history_path(Path::new("/dev/null"), "windows"), &[]).unwrap_err();
+        assert!(e.to_string().starts_with("could not create /dev/null:"), "{}", e);
+    }
+
+    #[test]
+    fn focus_events_keep_the_most_recent_windows_first() {
+        let mut mru = Vec::new();
+        let events = [MruEvent::Focus(1), MruEvent::Focus(2), MruEvent::Focus(3),
+                      MruEvent::Focus(1), MruEvent::Close(2), MruEvent::Focus(4)];
+        for &event in &events {
+            update_mru(&mut mru, event, 3);
+        }
+        assert_eq!(mru, vec![4, 1, 3]);
+
+        update_mru(&mut mru, MruEvent::Close(7), 3);
+        assert_eq!(mru, vec![4, 1, 3]);
+    }
+
+    #[test]
+    fn windows_sort_by_the_daemon_history() {
+        let mut windows = vec![window(1, None, "a"), window(2, None, "b"), window(3, None, "c")];
+        sort_by_mru(&mut windows, &[1003, 1001]);
+        assert_eq!(names(&windows), vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn retry_gives_up_after_the_retries() {
+        let none = Duration::from_millis(0);
+        let mut calls = 0;
+        let result = retry(2, none, none, || {
+            calls += 1;
+            if calls < 3 { Err(calls) } else { Ok("connected") }
+        });
+        assert_eq!(result, Ok("connected"));
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let result: Result<(), _> = retry(1, none, none, || {
+            calls += 1;
+            Err(calls)
+        });
+        assert_eq!(result, Err(2));
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_cap() {
+        let max = Duration::from_millis(5000);
+        assert_eq!(next_delay(Duration::from_millis(100), max), Duration::from_millis(200));
+        assert_eq!(next_delay(Duration::from_millis(4000), max), max);
+        assert_eq!(next_delay(Duration::new(u64::MAX, 0), max), max);
+    }
+
+    #[test]
+    fn scratchpad_windows_are_listed_apart() {
+        let mut hidden = window(2, Some("Keepassxc"), "passwords");
+        hidden.workspace = Some("__i3_scratch".to_owned());
+        let tree = tree_node(&[window(1, Some("URxvt"), "~"), hidden]);
+
+        assert_eq!(names(&get_windows_names(&tree, &Ignores::new(true))), vec!["~"]);
+        assert_eq!(names(&get_scratchpad_windows(&tree, &Ignores::new(true))), vec!["passwords"]);
+    }
+
+    #[test]
+    fn windows_move_to_the_scratchpad_by_id() {
+        let w = window(7, Some("URxvt"), "~");
+        assert_eq!(move_to_scratchpad_command(&w), "[id=\"7\"] move scratchpad");
+    }
+
+    #[test]
+    fn marks_are_matched_literally() {
+        let mark = |name: &str| Mark { name: name.to_owned() };
+        assert_eq!(mark("mail").to_select_string(), "[con_mark=\"^mail$\"]");
+        assert_eq!(focus_command(&mark("a.b"), false), "[con_mark=\"^a\\\\.b$\"] focus");
+        assert_eq!(mark("say \"hi\"").to_select_string(), "[con_mark=\"^say \\\"hi\\\"$\"]");
+    }
+
+    #[test]
+    fn mark_names_are_quoted() {
+        let w = window(7, Some("URxvt"), "~");
+        assert_eq!(mark_command(&w, "mail"), "[id=\"7\"] mark \"mail\"");
+        assert_eq!(mark_command(&w, "my term"), "[id=\"7\"] mark \"my term\"");
+    }
+
+    #[test]
+    fn marks_are_shown_joined_by_commas() {
+        let mut node = empty_node(reply::NodeType::Con, 42, Some("~".to_owned()));
+        node.window = Some(7);
+        node.class_name = Some("URxvt".to_owned());
+        node.marks = vec!["a".to_owned(), "term".to_owned()];
+        let w = window_from_node(&node, Location::default(), None).unwrap();
+        assert_eq!(w.marks, vec!["a", "term"]);
+
+        let windows = vec![w, window(8, Some("XTerm"), "top")];
+        let layout = Layout {
+            padding: 6,
+            marks_padding: Some(max_marks_size(&windows) + 1),
+            ..Layout::default()
+        };
+        assert_eq!(windows[0].label(&layout), "a,term URxvt ~");
+        assert_eq!(windows[1].label(&layout), "       XTerm top");
+        let template = "{mark}: {name}".parse::<Template>().unwrap();
+        assert_eq!(template.render(&windows[0]), "a,term: ~");
+    }
+
+    fn answer_with(choice: &str) -> MockBackend {
+        MockBackend { choice: choice.to_owned() }
+    }
+
+    #[test]
+    fn menu_line_breaks_do_not_get_in_the_way() {
+        let mapping = mapping(vec![window(1, None, "~"), window(2, None, "top")]);
+        for answer in &["top\n", "top\r\n", "  top  \n"] {
+            let (selected, text) = choose(&answer_with(answer), &mapping, None).unwrap();
+            assert_eq!(selected.map(|s| s.to_select_string()), Some("[id=\"2\"]".to_owned()));
+            assert_eq!(text, "top");
+        }
+
+        let (selected, text) = choose(&answer_with("\n"), &mapping, None).unwrap();
+        assert!(selected.is_none());
+        assert_eq!(text, "");
+    }
+
+    #[test]
+    fn same_named_workspaces_are_told_apart_by_output() {
+        let on = |name: &str, output: &str| {
+            Workspace { output: output.to_owned(), ..workspace(name, -1) }
+        };
+        let mapping = workspace_mapping(vec![on("mail", "eDP-1"), on("mail", "HDMI-1"),
+                                             workspace("1", 1)], None);
+        let labels = mapping.iter().map(|e| e.0.as_str()).collect::<Vec<_>>();
+        assert_eq!(labels, vec!["mail (eDP-1)", "mail (HDMI-1)", "1"]);
+
+        let picked = find_selection(&mapping, "mail (HDMI-1)").unwrap();
+        assert_eq!(picked.to_select_string(), "mail");
+    }
+
+    #[test]
+    fn dry_run_sends_nothing() {
+        let mut i3 = MockConnection::default();
+        let options = SendOptions { dry_run: true, ..SendOptions::default() };
+        let mut out = Vec::new();
+        send_command(&mut i3, "workspace \"mail\"", options, &mut out).unwrap();
+        assert!(i3.commands.is_empty());
+        assert_eq!(String::from_utf8(out).unwrap(), "workspace \"mail\"\n");
+
+        let mut out = Vec::new();
+        send_command(&mut i3, "workspace \"mail\"", SendOptions::default(), &mut out).unwrap();
+        assert_eq!(i3.commands, vec!["workspace \"mail\""]);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn variables_expand_in_menu_commands() {
+        env::set_var("QUICKSWITCH_I3_TEST_BIN", "/opt/menus");
+        let mut unset = Vec::new();
+        assert_eq!(expand_env("$QUICKSWITCH_I3_TEST_BIN/mymenu", &mut unset), "/opt/menus/mymenu");
+        assert_eq!(expand_env("${QUICKSWITCH_I3_TEST_BIN}x", &mut unset), "/opt/menusx");
+        assert_eq!(expand_env("10$", &mut unset), "10$");
+        assert_eq!(expand_env("cat > $1; echo $2", &mut unset), "cat > $1; echo $2");
+        assert!(unset.is_empty());
+
+        assert_eq!(expand_env("$QUICKSWITCH_I3_TEST_UNSET-x", &mut unset), "-x");
+        assert_eq!(unset, vec!["QUICKSWITCH_I3_TEST_UNSET"]);
+    }
+
+    #[test]
+    fn leading_tilde_is_home() {
+        let home = env::var("HOME").unwrap_or_default();
+        let mut unset = Vec::new();
+        assert_eq!(expand_env("~/bin/mymenu", &mut unset), format!("{}/bin/mymenu", home));
+        assert_eq!(expand_env("~", &mut unset), home);
+        assert_eq!(expand_env("a~/b", &mut unset), "a~/b");
+        assert_eq!(expand_env("~root", &mut unset), "~root");
+    }
+
+    #[test]
+    fn menus_are_sized_to_their_entries() {
+        assert_eq!(menu_lines(3, 20), 3);
+        assert_eq!(menu_lines(50, 20), 20);
+        assert_eq!(with_lines("dmenu -i -l 20", LINES_FLAG, 3), "dmenu -i -l 3");
+        assert_eq!(with_lines("rofi -dmenu", LINES_FLAG, 5), "rofi -dmenu -l 5");
+
+        // the menu prints the argument following -l
+        let menu = DmenuBackend {
+            command: "sh -c 'cat >/dev/null; printf %s \"$2\"' sh".to_owned(),
+            indexed: false,
+            verbose: false,
+            max_lines: Some(20),
+            lines_flag: LINES_FLAG,
+        };
+        assert_eq!(menu.choose("a\nb", None).unwrap().text, "2");
+    }
+
+    #[test]
+    fn reversed_entries_keep_their_rofi_index() {
+        let windows = vec![window(1, None, "a"), window(2, None, "b"), window(3, None, "c")];
+        let mut mapping = mapping(windows);
+        mapping.reverse();
+        assert_eq!(menu_options(&mapping), "c\nb\na");
+
+        // rofi's first row is the last window
+        let (selected, _) = resolve_selection(&mapping, "0 c", true);
+        assert_eq!(selected.map(|s| s.to_select_string()), Some("[id=\"3\"]".to_owned()));
+        let (selected, _) = resolve_selection(&mapping, "2 a", true);
+        assert_eq!(selected.map(|s| s.to_select_string()), Some("[id=\"1\"]".to_owned()));
+    }
+
+    #[test]
+    fn single_column_entries_are_not_padded() {
+        let w = window(1, Some("URxvt"), "~/src");
+        let layout = |columns| Layout { padding: 12, columns: columns, ..Layout::default() };
+        assert_eq!(w.label(&layout(Columns::All)), "URxvt       ~/src");
+        assert_eq!(w.label(&layout(Columns::TitleOnly)), "~/src");
+        assert_eq!(w.label(&layout(Columns::ClassOnly)), "URxvt");
+    }
+
+    #[test]
+    fn windows_sharing_a_title_are_both_selectable() {
+        let layout = Layout { columns: Columns::TitleOnly, ..Layout::default() };
+        let mut mapping: Mapping = Vec::new();
+        for w in [window(1, Some("URxvt"), "~"), window(2, Some("XTerm"), "~")] {
+            push_unique(&mut mapping, w.label(&layout), Box::new(w));
+        }
+        assert_eq!(menu_options(&mapping), "~\n~ (2)");
+        let second = find_selection(&mapping, "~ (2)").map(|s| s.to_select_string());
+        assert_eq!(second, Some("[id=\"2\"]".to_owned()));
+    }
+
+    #[test]
+    fn browser_suffixes_are_stripped() {
+        let smart = SMART_TITLE_SUFFIXES.iter().map(|p| Regex::new(p).unwrap()).collect::<Vec<_>>();
+        assert_eq!(strip_suffixes("My Page — Mozilla Firefox", &smart), "My Page");
+        assert_eq!(strip_suffixes("Inbox - Chromium", &smart), "Inbox");
+        assert_eq!(strip_suffixes("Mozilla Firefox", &smart), "Mozilla Firefox");
+        assert_eq!(strip_suffixes("Chromium - notes.txt", &smart), "Chromium - notes.txt");
+
+        let custom = [Regex::new(r" \[\d+\]$").unwrap()];
+        assert_eq!(strip_suffixes("vim [2]", &custom), "vim");
+    }
+
+    #[test]
+    fn swap_names_the_second_window_by_container() {
+        let first = window(1, Some("URxvt"), "~");
+        let second = window(2, Some("Emacs"), "main.rs");
+        assert_eq!(swap_command(&first, &second), "[id=\"1\"] swap container with con_id 1002");
+
+        let wayland = Window { id: None, con_id: 5, ..window(0, Some("foot"), "~") };
+        assert_eq!(swap_command(&wayland, &first),
+                   "[con_id=\"5\"] swap container with con_id 1001");
+    }
+
+    #[test]
+    fn fullscreen_focuses_first_unless_told_not_to() {
+        let w = window(7, Some("mpv"), "film.mkv");
+        assert_eq!(fullscreen_command(&w, true), "[id=\"7\"] focus, fullscreen toggle");
+        assert_eq!(fullscreen_command(&w, false), "[id=\"7\"] fullscreen toggle");
+    }
+
+    #[test]
+    fn classes_align_left_or_right() {
+        let windows = [window(1, Some("URxvt"), "~"), window(2, Some("Firefox"), "News")];
+        let labels = |align| {
+            let layout = Layout { padding: 9, gap: 2, align: align, ..Layout::default() };
+            windows.iter().map(|w| w.label(&layout)).collect::<Vec<_>>()
+        };
+        assert_eq!(labels(Align::Left), vec!["URxvt    ~", "Firefox  News"]);
+        assert_eq!(labels(Align::Right), vec!["  URxvt  ~", "Firefox  News"]);
+
+        let layout = Layout { padding: 9, columns: Columns::ClassOnly, ..Layout::default() };
+        assert_eq!(windows[0].label(&layout), "URxvt");
+        let untitled = window(3, Some("URxvt"), "");
+        assert_eq!(untitled.label(&Layout { padding: 9, ..Layout::default() }), "URxvt");
+    }
+
+    #[test]
+    fn custom_keys_pick_the_action() {
+        assert_eq!(custom_key_action(10), Some(KeyAction::Focus));
+        assert_eq!(custom_key_action(11), Some(KeyAction::Bring));
+        assert_eq!(custom_key_action(12), Some(KeyAction::Close));
+        assert_eq!(custom_key_action(13), None);
+        assert_eq!(custom_key_action(0), None);
+
+        let w = window(7, Some("URxvt"), "~");
+        assert_eq!(key_action_command(KeyAction::Focus, &w), "[id=\"7\"] focus");
+        assert_eq!(key_action_command(KeyAction::Bring, &w),
+                   "[id=\"7\"] move workspace current; [id=\"7\"] focus");
+        assert_eq!(key_action_command(KeyAction::Close, &w), "[id=\"7\"] kill");
+    }
+
+    #[test]
+    fn menu_exit_codes_are_kept() {
+        let menu = DmenuBackend {
+            command: "sh -c 'cat >/dev/null; echo b; exit 11'".to_owned(),
+            indexed: false,
+            verbose: false,
+            max_lines: None,
+            lines_flag: LINES_FLAG,
+        };
+        let output = menu.choose("a\nb", None).unwrap();
+        assert_eq!(output, MenuOutput { text: "b\n".to_owned(), exit_code: Some(11) });
+    }
+
+    fn dmenu(command: &str) -> DmenuBackend {
+        DmenuBackend {
+            command: command.to_owned(),
+            indexed: false,
+            verbose: false,
+            max_lines: None,
+            lines_flag: LINES_FLAG,
+        }
+    }
+
+    #[test]
+    fn failed_menus_count_as_cancelled() {
+        let output = dmenu("sh -c 'cat >/dev/null; exit 1'").choose("a\nb", None).unwrap();
+        assert_eq!(output, MenuOutput { text: String::new(), exit_code: Some(1) });
+        assert!(output.cancelled());
+
+        let output = dmenu("sh -c 'cat >/dev/null; echo b; exit 11'").choose("a\nb", None).unwrap();
+        assert!(!output.cancelled());
+        assert!(!MenuOutput { text: "b".to_owned(), exit_code: Some(0) }.cancelled());
+        assert!(!MenuOutput { text: "b".to_owned(), exit_code: None }.cancelled());
+    }
+
+    #[test]
+    fn bad_socket_paths_are_reported() {
+        let err = connect(Some("/nonexistent/i3/ipc-socket"), 0).unwrap_err();
+        assert!(err.to_string().starts_with("could not use i3 socket '/nonexistent/i3/ipc-socket'"),
+                "{}", err);
+        assert_eq!(check_socket("/").unwrap_err().to_string(), "'/' is not a socket");
+    }
+
+    #[test]
+    fn numbered_entries_still_resolve() {
+        let layout = Layout { columns: Columns::TitleOnly, ..Layout::default() };
+        let windows = (1..11).map(|i| window(i, None, &format!("w{}", i))).collect::<Vec<_>>();
+        let mut mapping = mapping_with(windows, &layout);
+        push_header(&mut mapping, "end");
+        number_entries(&mut mapping);
+
+        let options = menu_options(&mapping);
+        let lines = options.lines().collect::<Vec<_>>();
+        assert_eq!(lines[2], " 3: w3");
+        assert_eq!(lines[9], "10: w10");
+        assert_eq!(lines[10], "── end ──");
+        let third = find_selection(&mapping, " 3: w3").map(|s| s.to_select_string());
+        assert_eq!(third, Some("[id=\"3\"]".to_owned()));
+        // dmenu can hand the line back with its padding trimmed
+        let third = find_selection(&mapping, "3: w3").map(|s| s.to_select_string());
+        assert_eq!(third, Some("[id=\"3\"]".to_owned()));
+    }
+
+    #[test]
+    fn windows_move_to_the_next_or_previous_workspace() {
+        let w = window(7, Some("URxvt"), "~");
+        assert_eq!(move_relative_command(&w, true, false),
+                   "[id=\"7\"] move container to workspace next");
+        assert_eq!(move_relative_command(&w, false, false),
+                   "[id=\"7\"] move container to workspace prev");
+        assert_eq!(move_relative_command(&w, true, true),
+                   "[id=\"7\"] move container to workspace next; [id=\"7\"] focus");
+    }
+
+    #[test]
+    fn urgent_workspaces_are_marked() {
+        let mut chat = workspace("chat", -1);
+        chat.urgent = true;
+        let mapping = workspace_mapping(vec![workspace("1", 1), chat], Some("!!"));
+        assert_eq!(menu_options(&mapping), "   1\n!! chat");
+        let picked = find_selection(&mapping, "!! chat").map(|s| s.to_select_string());
+        assert_eq!(picked, Some("chat".to_owned()));
+    }
+
+    #[test]
+    fn markup_escapes_titles_and_colors_the_class() {
+        let w = window(7, Some("URxvt"), "a < b & c");
+        let color = Some("#888".to_owned());
+        let layout = Layout { padding: 6, markup_color: color, ..Layout::default() };
+        assert_eq!(w.label(&layout), "<span foreground=\"#888\">URxvt </span>a &lt; b &amp; c");
+
+        let template = Layout { template: "{class}: {name}".parse().ok(), ..layout };
+        assert_eq!(w.label(&template), "URxvt: a &lt; b &amp; c");
+
+        let mapping = mapping_with(vec![w], &template);
+        let picked = find_selection(&mapping, "URxvt: a &lt; b &amp; c");
+        assert_eq!(picked.map(|s| s.to_select_string()), Some("[id=\"7\"]".to_owned()));
+    }
+
+    #[test]
+    fn icons_follow_the_label_in_rofi_rows() {
+        assert_eq!(menu_row("URxvt ~", Some("terminal")), "URxvt ~\0icon\x1fterminal");
+        assert_eq!(menu_row("URxvt ~", None), "URxvt ~");
+
+        let mut term = window(1, Some("URxvt"), "~");
+        term.icon = Some("terminal".to_owned());
+        let layout = Layout { columns: Columns::TitleOnly, ..Layout::default() };
+        let mut mapping = mapping_with(vec![term, window(2, Some("Emacs"), "main.rs")], &layout);
+        push_header(&mut mapping, "end");
+        assert_eq!(menu_options(&mapping), "~\0icon\x1fterminal\nmain.rs\n── end ──");
+    }
+
+    #[test]
+    fn desktop_entries_are_found_by_class_or_id() {
+        let root = env::temp_dir().join(format!("quickswitch-i3-desktop-{}", process::id()));
+        let (first, second) = (root.join("first"), root.join("second"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        let write = |dir: &Path, file: &str, content: &str| {
+            fs::write(dir.join(file), content).unwrap();
+        };
+        write(&first, "firefox.desktop", "[Desktop Entry]\nName=Firefox\nIcon=firefox\n");
+        write(&first, "emacs.desktop", "[Desktop Entry]\nName=Emacs\nStartupWMClass=Emacs\n");
+        write(&second, "firefox.desktop", "[Desktop Entry]\nName=Other\nIcon=other\n");
+        write(&second, "notes.txt", "Icon=nothing\n");
+
+        let entries = DesktopEntries::scan(&[first, second]);
+        fs::remove_dir_all(&root).unwrap();
+
+        let icon = entries.lookup("Firefox").and_then(|e| e.icon.as_ref()).map(|s| s.as_str());
+        assert_eq!(icon, Some("firefox"));
+        assert_eq!(entries.lookup("emacs").and_then(|e| e.name.clone()), Some("Emacs".to_owned()));
+        assert_eq!(entries.lookup("emacs").and_then(|e| e.icon.clone()), None);
+        assert!(entries.lookup("notes").is_none());
+        assert!(entries.lookup("URxvt").is_none());
+    }
+
+    #[test]
+    fn aliases_match_whole_classes() {
+        let aliases = [
+            ("jetbrains-idea", "IntelliJ"),
+            ("Gimp.*", "GIMP"),
+            ("Gimp-2.10", "Old GIMP"),
+            ("fire", "Fire"),
+        ];
+        let aliases = aliases.iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect::<BTreeMap<_, _>>();
+        let aliases = ClassAliases::new(&aliases);
+
+        assert_eq!(aliases.resolve("jetbrains-idea"), Some("IntelliJ"));
+        // an exact key wins over a pattern matching it too
+        assert_eq!(aliases.resolve("Gimp-2.10"), Some("Old GIMP"));
+        assert_eq!(aliases.resolve("Gimp-2.99"), Some("GIMP"));
+        assert_eq!(aliases.resolve("firefox"), None);
+        assert_eq!(aliases.resolve("jetbrains-idea-ce"), None);
+        assert!(ClassAliases::new(&BTreeMap::new()).is_empty());
+    }
+
+    #[test]
+    fn every_failed_part_of_a_command_is_reported() {
+        let outcome = |error: Option<&str>| reply::CommandOutcome {
+            success: error.is_none(),
+            error: error.map(|e| e.to_owned()),
+        };
+        let outcomes = vec![outcome(Some("a")), outcome(None), outcome(Some("b"))];
+        let reply = reply::Command { outcomes };
+        assert_eq!(command_result("x; y; z", &reply).unwrap_err().to_string(),
+                   "i3 could not run 'x; y; z': a; b");
+        assert!(command_result("x", &reply::Command { outcomes: vec![outcome(None)] }).is_ok());
+    }
+
+    #[test]
+    fn terminal_answers_are_numbers_or_text() {
+        let options = "~\ntop\0icon\x1fterminal\nmain.rs";
+        assert_eq!(terminal_choice(options, "2\n"), "top");
+        assert_eq!(terminal_choice(options, " 3 "), "main.rs");
+        assert_eq!(terminal_choice(options, "0"), "0");
+        assert_eq!(terminal_choice(options, "4"), "4");
+        assert_eq!(terminal_choice(options, "mail\n"), "mail");
+    }
+
+    #[test]
+    fn terminal_menu_lists_numbered_options() {
+        let menu = TerminalBackend::new(io::Cursor::new("2\n"), Vec::new());
+        let output = menu.choose("~\ntop", Some("focus")).unwrap();
+        assert_eq!(output, MenuOutput { text: "top".to_owned(), exit_code: None });
+        assert_eq!(String::from_utf8(menu.writer.into_inner()).unwrap(), "1) ~\n2) top\nfocus> ");
+
+        let menu = TerminalBackend::new(io::Cursor::new(""), Vec::new());
+        assert!(menu.choose("~\ntop", None).unwrap().cancelled());
+    }
+
+    #[test]
+    fn fuzzy_matches_rank_runs_and_word_starts_first() {
+        assert!(fuzzy_score("slk", "Slack").is_some());
+        assert_eq!(fuzzy_score("kcals", "Slack"), None);
+        assert!(fuzzy_score("sl", "Slack") > fuzzy_score("sl", "Sublime Text Library"));
+        assert!(fuzzy_score("tl", "Text Library") > fuzzy_score("tl", "kettle"));
+
+        let layout = Layout { columns: Columns::TitleOnly, ..Layout::default() };
+        let windows = vec![
+            window(1, None, "Sublime Text Library"),
+            window(2, None, "Slack"),
+            window(3, None, "mail"),
+        ];
+        let filtered = fuzzy_filter(mapping_with(windows, &layout), "sl");
+        let labels = filtered.iter().map(|e| e.0.as_str()).collect::<Vec<_>>();
+        assert_eq!(labels, vec!["Slack", "Sublime Text Library"]);
+    }
+
+    #[test]
+    fn rofi_script_lists_then_answers() {
+        let listing = RofiScriptBackend::new(0, None, false, Vec::new());
+        let output = listing.choose("~\ntop", Some("focus")).unwrap();
+        assert!(output.cancelled());
+        assert_eq!(String::from_utf8(listing.writer.into_inner()).unwrap(),
+                   "\0prompt\x1ffocus\n\0no-custom\x1ftrue\n\0use-hot-keys\x1ftrue\n~\ntop\n");
+
+        let picked = RofiScriptBackend::new(1, Some("top".to_owned()), false, Vec::new());
+        assert_eq!(picked.choose("~\ntop", Some("focus")).unwrap(),
+                   MenuOutput { text: "top".to_owned(), exit_code: None });
+        assert!(picked.writer.into_inner().is_empty());
+
+        let key = RofiScriptBackend::new(11, Some("top".to_owned()), true, Vec::new());
+        assert_eq!(key.choose("~\ntop", None).unwrap().exit_code, Some(11));
+    }
+
+    #[test]
+    fn menu_keys_act_on_windows_and_workspaces() {
+        let w = window(7, Some("URxvt"), "~");
+        let ws = workspace("web site", -1);
+        assert_eq!(menu_entry_command(&ws, None), Some("workspace \"web site\"".to_owned()));
+        assert_eq!(menu_entry_command(&ws, Some(KeyAction::Focus)),
+                   Some("workspace \"web site\"".to_owned()));
+        assert_eq!(menu_entry_command(&ws, Some(KeyAction::Bring)),
+                   Some("move container to workspace \"web site\"".to_owned()));
+        assert_eq!(menu_entry_command(&ws, Some(KeyAction::Close)), None);
+
+        assert_eq!(menu_entry_command(&w, None), Some("[id=\"7\"] focus".to_owned()));
+        assert_eq!(menu_entry_command(&w, Some(KeyAction::Bring)),
+                   Some("[id=\"7\"] move workspace current; [id=\"7\"] focus".to_owned()));
+        assert_eq!(menu_entry_command(&Mark { name: "a".to_owned() }, None), None);
+    }
+
+    #[test]
+    fn selection_lines_are_tab_separated() {
+        assert_eq!(selection_line(&window(7, Some("URxvt"), "~ src")), "7\tURxvt\t~ src");
+        let wayland = Window { id: None, ..window(0, Some("foot"), "~") };
+        assert_eq!(selection_line(&wayland), "\tfoot\t~");
+        assert_eq!(selection_line(&window(8, None, "x")), "8\t\tx");
+    }
+
+    #[test]
+    fn ignore_lists_split_on_commas_and_add_to_the_defaults() {
+        let values = ["a, b".to_owned(), "c".to_owned(), ",".to_owned()];
+        assert_eq!(split_list(&values), vec!["a", "b", "c"]);
+
+        let mut ignores = Ignores::new(true);
+        ignores.extend(&[], &split_list(&values));
+        let mut expected = IGNORE_WINDOW_CLASS.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        expected.extend(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()]);
+        assert_eq!(ignores.classes, expected);
+    }
+
+    #[test]
+    fn docks_are_hidden_unless_included() {
+        let mut node = empty_node(reply::NodeType::Con, 42, Some("polybar".to_owned()));
+        node.window = Some(7);
+        node.window_type = Some("dock".to_owned());
+        let mut ignores = Ignores::new(true);
+        assert!(!filter_node(&node, &ignores));
+
+        node.window_type = Some("normal".to_owned());
+        assert!(filter_node(&node, &ignores));
+
+        node.window_type = Some("dock".to_owned());
+        ignores.include_docks();
+        assert!(filter_node(&node, &ignores));
+        assert!(filter_node(&node, &Ignores { types: Vec::new(), ..Ignores::new(false) }));
+    }
+
+    #[test]
+    fn unnamed_windows_need_a_placeholder() {
+        let mut node = empty_node(reply::NodeType::Con, 42, None);
+        node.window = Some(7);
+        let mut ignores = Ignores::new(true);
+        assert!(!filter_node(&node, &ignores));
+
+        ignores.include_unnamed("<unnamed>");
+        assert!(filter_node(&node, &ignores));
+        let mut tree = tree_node(&[window(1, Some("URxvt"), "~")]);
+        tree.nodes[0].nodes[0].nodes.push(node);
+        let windows = get_windows_names(&tree, &ignores);
+        assert_eq!(names(&windows), vec!["~", "<unnamed>"]);
+        assert_eq!(windows[1].to_select_string(), "[id=\"7\"]");
+    }
+
+    #[test]
+    fn pid_is_taken_from_the_node() {
+        let mut node = empty_node(reply::NodeType::Con, 42, Some("~".to_owned()));
+        node.window = Some(7);
+        node.pid = Some(4242);
+        let w = window_from_node(&node, Location::default(), None).unwrap();
+        assert_eq!(w.pid, Some(4242));
+        assert_eq!("{pid}".parse::<Template>().unwrap().render(&w), "4242");
+
+        node.pid = None;
+        assert_eq!(window_from_node(&node, Location::default(), None).unwrap().pid, None);
+        assert_eq!(max_pid_size(&[w, window(8, None, "x")]), 4);
+    }
+
+    #[test]
+    fn killing_a_pid_signals_the_process() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let w = Window { pid: Some(child.id() as i32), ..window(7, Some("URxvt"), "~") };
+        let mut out = Vec::new();
+        let dry_run = SendOptions { verbose: false, dry_run: true };
+        kill_pid(&w, KillSignal::Term, dry_run, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("kill -TERM {}\n", child.id()));
+        assert!(child.try_wait().unwrap().is_none());
+
+        let mut out = Vec::new();
+        kill_pid(&w, KillSignal::Kill, SendOptions::default(), &mut out).unwrap();
+        assert!(out.is_empty());
+        assert!(!child.wait().unwrap().success());
+
+        let gone = Window { pid: Some(i32::MAX), ..w };
+        let err = kill_pid(&gone, KillSignal::Term, SendOptions::default(), &mut Vec::new());
+        assert!(err.unwrap_err().to_string().contains("No such process"));
+        let unknown = Window { pid: None, ..gone };
+        assert!(kill_pid(&unknown, KillSignal::Term, SendOptions::default(), &mut Vec::new())
+                .is_err());
+    }
+
+    #[test]
+    fn floating_windows_get_the_floating_marker() {
+        let mut popup = window(2, Some("Pavucontrol"), "Volume");
+        popup.floating = true;
+        let tree = tree_node(&[window(1, Some("URxvt"), "~"), popup]);
+        let windows = get_windows_names(&tree, &Ignores::new(true));
+        assert_eq!(windows.iter().map(|w| w.floating).collect::<Vec<_>>(), vec![false, true]);
+
+        let layout = Layout {
+            columns: Columns::TitleOnly,
+            marker_floating: Some(FLOATING_MARKER.to_owned()),
+            ..Layout::default()
+        };
+        let marker = format!("{} ", FLOATING_MARKER);
+        assert_eq!(windows[1].label(&layout), format!("{}Volume", marker));
+        assert_eq!(windows[0].label(&layout), format!("{}~", " ".repeat(marker.width())));
+        let template = "{floating}{name}".parse::<Template>().unwrap();
+        assert_eq!(template.render(&windows[1]), format!("{}Volume", FLOATING_MARKER));
+        assert_eq!(template.render(&windows[0]), "~");
+    }
+
+    #[test]
+    fn geometry_follows_the_padded_title() {
+        let mut w = window(7, Some("URxvt"), "~");
+        w.rect = (10, 20, 800, 600);
+        assert_eq!(w.geometry(), "800x600+10+20");
+        w.rect = (-5, 0, 10, 10);
+        assert_eq!(w.geometry(), "10x10-5+0");
+        w.rect = (10, 20, 800, 600);
+
+        let windows = vec![w, window(8, Some("Emacs"), "main.rs")];
+        let layout = Layout {
+            padding: 6,
+            title_padding: Some(max_title_size(&windows, None) + 1),
+            ..Layout::default()
+        };
+        assert_eq!(windows[0].label(&layout), "URxvt ~       800x600+10+20");
+        assert_eq!(windows[1].label(&layout), "Emacs main.rs 800x600+0+0");
+    }
+
+    #[test]
+    fn menu_command_comes_from_the_flag_then_env_then_config() {
+        let resolve = |flag, env, config| resolve_dmenu_command(flag, env, config, "dmenu");
+        assert_eq!(resolve(Some("rofi -dmenu"), Some("bemenu"), Some("wofi")), "rofi -dmenu");
+        assert_eq!(resolve(None, Some("bemenu"), Some("wofi")), "bemenu");
+        assert_eq!(resolve(None, None, Some("wofi")), "wofi");
+        assert_eq!(resolve(None, None, None), "dmenu");
+    }
+
+    #[test]
+    fn separator_replaces_the_padding() {
+        let windows = vec![window(7, Some("URxvt"), "~"), window(8, Some("Emacs-gtk"), "main.rs")];
+        let piped = Layout {
+            padding: max_class_name_size(&windows) + 1,
+            separator: Some(" | ".to_owned()),
+            ..Layout::default()
+        };
+        assert_eq!(windows[0].label(&piped), "URxvt | ~");
+        assert_eq!(windows[1].label(&piped), "Emacs-gtk | main.rs");
+
+        let tabbed = Layout { separator: Some("\t".to_owned()), ..piped };
+        assert_eq!(windows[0].label(&tabbed), "URxvt\t~");
+
+        let geometry = Layout { title_padding: Some(8), ..tabbed };
+        assert_eq!(windows[0].label(&geometry), "URxvt\t~\t800x600+0+0");
+    }
+
+    #[test]
+    fn presets_resolve_to_their_commands() {
+        let preset = |name: &str| name.parse::<MenuPreset>().unwrap();
+        assert_eq!(preset("dmenu").command(), DEFAULT_DMENU_COMMAND);
+        assert_eq!(preset("bemenu").command(), "bemenu -i -l 20");
+        assert_eq!(preset("wofi").command(), "wofi --dmenu -i");
+        assert_eq!(preset("fuzzel").command(), "fuzzel --dmenu");
+        assert_eq!(preset("wofi").lines_flag(), "-L");
+        assert_eq!(preset("fuzzel").lines_flag(), LINES_FLAG);
+        assert_eq!(with_lines("wofi --dmenu -L 20", "-L", 4), "wofi --dmenu -L 4");
+        assert!("rofi".parse::<MenuPreset>().is_err());
+    }
+
+    #[test]
+    fn workspace_names_are_quoted_for_i3() {
+        assert_eq!(workspace_command("my project"), "workspace \"my project\"");
+        assert_eq!(workspace_command("a \"b\" \\c"), "workspace \"a \\\"b\\\" \\\\c\"");
+        assert_eq!(split(&workspace_command("my project")),
+                   Some(("workspace".to_owned(), vec!["my project".to_owned()])));
+        assert_eq!(split(&workspace_command("a \"b\" \\c")),
+                   Some(("workspace".to_owned(), vec!["a \"b\" \\c".to_owned()])));
+    }
+
+    #[test]
+    fn clearing_urgency_focuses_twice() {
+        let w = window(7, Some("URxvt"), "~");
+        assert_eq!(focus_command(&w, false), "[id=\"7\"] focus");
+        assert_eq!(focus_command(&w, true), "[id=\"7\"] focus; [id=\"7\"] focus");
+    }
+}
@@ -0,0 +1,135 @@
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// The binary, run where there is no i3 nor config file to be found
+fn quickswitch() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_quickswitch-i3"));
+    command.env_remove("I3SOCK").env_remove("SWAYSOCK").env("PATH", "/nonexistent")
+        .env("XDG_CONFIG_HOME", "/nonexistent");
+    command
+}
+
+#[test]
+fn no_i3_is_an_error_not_a_panic() {
+    let output = quickswitch().arg("--workspace").output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.starts_with("quickswitch-i3: could not connect to i3: is i3 (or sway) running?"),
+            "{}", stderr);
+}
+
+#[test]
+fn unparsable_config_file_is_an_error_not_a_panic() {
+    let dir = env::temp_dir().join(format!("quickswitch-i3-cli-{}", process::id()));
+    fs::create_dir_all(dir.join("quickswitch-i3")).unwrap();
+    fs::write(dir.join("quickswitch-i3").join("config.toml"), "ignore_names = 3\n").unwrap();
+
+    let output = quickswitch().env("XDG_CONFIG_HOME", &dir).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.starts_with("quickswitch-i3: could not parse config file"), "{}", stderr);
+}
+
+#[test]
+fn zero_title_width_is_rejected() {
+    let output = quickswitch().args(["--max-title-width", "0"]).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.contains("--max-title-width must be at least 1"), "{}", stderr);
+}
+
+#[test]
+fn runs_showing_no_menu_leave_the_menu_alone() {
+    for mode in &["--last", "--json", "--list"] {
+        let output = quickswitch().args(["--dmenu", "nonexistent-menu", mode]).output().unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.starts_with("quickswitch-i3: could not connect to i3"), "{}", stderr);
+        assert!(!stderr.contains("menu"), "{}", stderr);
+    }
+}
+
+// A stand-in i3 at `path`, answering every request as if it went fine;
+// returns the commands it was sent
+fn fake_i3(path: &Path) -> Arc<Mutex<Vec<String>>> {
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path).unwrap();
+    let commands = Arc::new(Mutex::new(Vec::new()));
+    let sent = commands.clone();
+    thread::spawn(move || for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        let mut header = [0; 14];
+        while stream.read_exact(&mut header).is_ok() {
+            let len = u32::from_le_bytes([header[6], header[7], header[8], header[9]]);
+            let message_type = u32::from_le_bytes([header[10], header[11], header[12], header[13]]);
+            let mut payload = vec![0; len as usize];
+            stream.read_exact(&mut payload).unwrap();
+            let reply: &[u8] = match message_type {
+                0 => {
+                    sent.lock().unwrap().push(String::from_utf8(payload).unwrap());
+                    b"[{\"success\":true}]"
+                }
+                _ => b"[]",
+            };
+            let mut message = b"i3-ipc".to_vec();
+            message.extend_from_slice(&(reply.len() as u32).to_le_bytes());
+            message.extend_from_slice(&message_type.to_le_bytes());
+            message.extend_from_slice(reply);
+            stream.write_all(&message).unwrap();
+        }
+    });
+    commands
+}
+
+// Which of the fake sockets `--last` was sent to, with I3SOCK set and
+// `--socket` and SWAYSOCK given or not
+fn last_sent_to(socket: bool, swaysock: bool) -> Vec<&'static str> {
+    let dir = env::temp_dir()
+        .join(format!("quickswitch-i3-sockets-{}-{}-{}", process::id(), socket, swaysock));
+    fs::create_dir_all(&dir).unwrap();
+    let paths: Vec<PathBuf> =
+        ["explicit", "sway", "i3"].iter().map(|name| dir.join(name)).collect();
+    let fakes: Vec<_> = paths.iter().map(|path| fake_i3(path)).collect();
+
+    let mut command = quickswitch();
+    command.env("I3SOCK", &paths[2]).args(["--connect-retries", "0", "--last"]);
+    if socket {
+        command.arg("--socket").arg(&paths[0]);
+    }
+    if swaysock {
+        command.env("SWAYSOCK", &paths[1]);
+    }
+    let output = command.output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    ["explicit", "sway", "i3"].iter().zip(&fakes)
+        .filter(|&(_, fake)| !fake.lock().unwrap().is_empty())
+        .map(|(&name, fake)| {
+            assert_eq!(*fake.lock().unwrap(), ["workspace back_and_forth"]);
+            name
+        })
+        .collect()
+}
+
+#[test]
+fn socket_flag_wins_over_swaysock_and_i3sock() {
+    assert_eq!(last_sent_to(true, true), ["explicit"]);
+}
+
+#[test]
+fn swaysock_wins_over_i3sock() {
+    assert_eq!(last_sent_to(false, true), ["sway"]);
+}
+
+#[test]
+fn i3sock_is_used_on_its_own() {
+    assert_eq!(last_sent_to(false, false), ["i3"]);
+}